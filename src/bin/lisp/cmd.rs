@@ -6,4 +6,12 @@ pub struct Cli {
     /// Parse input only
     #[arg(short, long)]
     pub parse: bool,
+
+    /// Line width for printed values
+    #[arg(short, long, default_value_t = lisp::Printer::DEFAULT_WIDTH)]
+    pub width: usize,
+
+    /// Most pending continuation frames before evaluation fails
+    #[arg(short, long, default_value_t = lisp::Evaluator::DEFAULT_STACK_LIMIT)]
+    pub stack_limit: usize,
 }
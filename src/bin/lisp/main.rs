@@ -1,9 +1,15 @@
 mod cmd;
 
 use clap::Parser as _;
+use lisp::Diagnostic;
 use lisp::Env;
 use lisp::Evaluator;
+use lisp::Lexer;
+use lisp::LispError;
 use lisp::Parser;
+use lisp::Printer;
+use lisp::Source;
+use lisp::Token;
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 use std::cell::RefCell;
@@ -11,6 +17,7 @@ use std::fs::OpenOptions;
 use std::io;
 use std::io::IsTerminal;
 use std::io::Read;
+use std::process;
 use std::rc::Rc;
 
 fn main() -> anyhow::Result<()> {
@@ -23,17 +30,29 @@ fn main() -> anyhow::Result<()> {
         let mut source = String::new();
         stdin.read_to_string(&mut source)?;
 
-        if cli.parse {
-            Ok(println!("{:#?}", Parser::parse(source)?))
+        let source = Source::new("<stdin>", source);
+        let result = if cli.parse {
+            Parser::parse_spanned(source).map(|(exprs, _)| exprs)
         } else {
-            Ok(println!("{:#?}", Evaluator::eval(source, &mut env)?))
+            Evaluator::eval_source_limited(source, &mut env, cli.stack_limit).map(|expr| vec![expr])
+        };
+
+        match result {
+            Ok(exprs) => exprs
+                .iter()
+                .for_each(|expr| println!("{}", Printer::pretty(expr, cli.width))),
+            Err(err) => {
+                eprintln!("{}", report(&err));
+                process::exit(1);
+            }
         }
+        Ok(())
     } else {
-        repl(&mut env)
+        repl(&mut env, &cli)
     }
 }
 
-fn repl(env: &mut Rc<RefCell<Env>>) -> anyhow::Result<()> {
+fn repl(env: &mut Rc<RefCell<Env>>, cli: &cmd::Cli) -> anyhow::Result<()> {
     let mut rl = DefaultEditor::new()?;
     let mut history = dirs::home_dir().unwrap();
     history.push(".lisp_history");
@@ -42,35 +61,35 @@ fn repl(env: &mut Rc<RefCell<Env>>) -> anyhow::Result<()> {
         println!("REPL: No previous history");
         OpenOptions::new()
             .create(true)
+            .truncate(false)
             .write(true)
             .open(history.as_path())?;
     }
 
-    let mut open_parens: i32 = 0;
     let mut buffer = String::new();
 
     loop {
-        let readline = rl.readline(if open_parens > 0 { ".. " } else { "-> " });
+        let readline = rl.readline(if buffer.is_empty() { "-> " } else { ".. " });
         match readline {
             Ok(line) => {
-                if line.trim().is_empty() {
+                if buffer.is_empty() && line.trim().is_empty() {
                     continue;
                 }
                 buffer.push_str(&line);
                 buffer.push('\n');
 
-                open_parens += line.chars().filter(|&c| c == '(').count() as i32;
-                open_parens -= line.chars().filter(|&c| c == ')').count() as i32;
-
-                match open_parens {
-                    0 => {
+                match open_parens(&buffer) {
+                    Some(0) => {
                         rl.add_history_entry(buffer.as_str().trim())?;
-                        println!("{:#?}", Evaluator::eval(buffer.as_str(), env)?);
+                        let source = Source::new("<repl>", buffer.as_str());
+                        match Evaluator::eval_source_limited(source, env, cli.stack_limit) {
+                            Ok(expr) => println!("{}", Printer::pretty(&expr, cli.width)),
+                            Err(err) => println!("{}", report(&err)),
+                        }
                         buffer.clear();
                     }
-                    ..0 => {
+                    Some(..0) => {
                         println!("REPL: BadRParen(s)");
-                        open_parens = 0;
                         buffer.clear();
                     }
                     _ => (),
@@ -89,3 +108,37 @@ fn repl(env: &mut Rc<RefCell<Env>>) -> anyhow::Result<()> {
     rl.save_history(history.as_path())?;
     Ok(())
 }
+
+/// Depth of unclosed parens in buffer, None while a string, block comment or
+/// datum comment is still open
+fn open_parens(buffer: &str) -> Option<i32> {
+    match Lexer::tokenize(buffer) {
+        Ok(tokens) => Some(
+            tokens
+                .iter()
+                .map(|token| match token {
+                    Token::LParen => 1,
+                    Token::RParen => -1,
+                    _ => 0,
+                })
+                .sum(),
+        ),
+        Err(err) => match err.downcast_ref::<LispError>() {
+            Some(
+                LispError::UnterminatedString
+                | LispError::UnterminatedComment
+                | LispError::MissingDatum,
+            ) => None,
+            _ => Some(0),
+        },
+    }
+}
+
+/// Render an error, with a source snippet when it is located
+fn report(err: &anyhow::Error) -> String {
+    if err.is::<Diagnostic>() {
+        err.to_string()
+    } else {
+        format!("error: {}", err)
+    }
+}
@@ -1,4 +1,6 @@
-use crate::expr::Expr;
+use crate::eval::Control;
+use crate::expr::{intrinsics::NATIVES, Arity, Expr};
+use crate::macros::{Alias, Macro};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -7,33 +9,136 @@ use std::rc::Rc;
 pub struct Env {
     parent: Option<Rc<RefCell<Env>>>,
     vars: HashMap<String, Expr>,
+    /// Macros defined in this scope, sharing the namespace of vars
+    macros: HashMap<String, Macro>,
+    /// Symbols renamed by syntax-rules expansions in this scope
+    aliases: HashMap<String, Alias>,
 }
 
 impl Env {
+    /// Global Env with every builtin procedure bound
     pub fn new() -> Self {
-        Default::default()
+        let mut env = Self::default();
+        for &(name, arity, func) in NATIVES {
+            env.define_native(name, arity, func);
+        }
+        for &(name, control) in Control::BINDINGS {
+            env.set(name, Expr::Control(control));
+        }
+        env
     }
 
     pub fn extend(parent: Rc<RefCell<Self>>) -> Self {
         Self {
-            vars: HashMap::new(),
             parent: Some(parent),
+            ..Default::default()
         }
     }
 
     pub fn get(&self, name: impl AsRef<str>) -> Option<Expr> {
-        match self.vars.get(name.as_ref().to_ascii_lowercase().as_str()) {
-            Some(value) => Some(value.clone()),
-            None => self
-                .parent
-                .as_ref()
-                .and_then(|o| o.borrow().get(name).clone()),
+        let key = name.as_ref().to_ascii_lowercase();
+        if let Some(value) = self.vars.get(&key) {
+            return Some(value.clone());
+        }
+        match self.aliases.get(&key) {
+            Some(alias) => alias.env.borrow().get(&alias.name),
+            None => self.parent.as_ref()?.borrow().get(key),
         }
     }
 
     pub fn set(&mut self, name: impl AsRef<str>, val: Expr) {
-        self.vars
-            .insert(name.as_ref().to_ascii_lowercase().into(), val);
+        let key = name.as_ref().to_ascii_lowercase();
+        if !self.macros.is_empty() {
+            self.macros.remove(&key);
+        }
+        self.vars.insert(key, val);
+    }
+
+    /// Macro bound to name, unless a nearer variable shadows it
+    pub fn get_macro(&self, name: impl AsRef<str>) -> Option<Macro> {
+        let key = name.as_ref().to_ascii_lowercase();
+        if self.vars.contains_key(&key) {
+            return None;
+        }
+        if let Some(transformer) = self.macros.get(&key) {
+            return Some(transformer.clone());
+        }
+        match self.aliases.get(&key) {
+            Some(alias) => alias.env.borrow().get_macro(&alias.name),
+            None => self.parent.as_ref()?.borrow().get_macro(key),
+        }
+    }
+
+    /// Name an alias stands for where it is not bound itself, following
+    /// aliases of aliases
+    pub fn unalias(&self, name: impl AsRef<str>) -> Option<String> {
+        let key = name.as_ref().to_ascii_lowercase();
+        if self.vars.contains_key(&key) || self.macros.contains_key(&key) {
+            return None;
+        }
+        match self.aliases.get(&key) {
+            Some(alias) => {
+                let resolved = alias.env.borrow().unalias(&alias.name);
+                Some(resolved.unwrap_or_else(|| alias.name.clone()))
+            }
+            None => self.parent.as_ref()?.borrow().unalias(key),
+        }
+    }
+
+    /// Let a renamed symbol stand for alias.name as bound in alias.env
+    pub fn set_alias(&mut self, name: impl AsRef<str>, alias: Alias) {
+        self.aliases
+            .insert(name.as_ref().to_ascii_lowercase(), alias);
+    }
+
+    /// Bind name to a macro in this scope, replacing any variable of that name
+    pub fn set_macro(&mut self, name: impl AsRef<str>, transformer: Macro) {
+        let key = name.as_ref().to_ascii_lowercase();
+        self.vars.remove(&key);
+        self.macros.insert(key, transformer);
+    }
+
+    /// Rebind an existing variable in the nearest Env defining it
+    ///
+    /// Returns false if no enclosing Env defines name
+    pub fn assign(env: &Rc<RefCell<Self>>, name: impl AsRef<str>, val: Expr) -> bool {
+        let mut env = env.clone();
+        let mut key = name.as_ref().to_ascii_lowercase();
+        loop {
+            // Release each Env before moving on, an alias may lead back to it
+            let next = {
+                let mut scope = env.borrow_mut();
+                if let Some(slot) = scope.vars.get_mut(&key) {
+                    *slot = val;
+                    return true;
+                }
+                match (scope.aliases.get(&key), &scope.parent) {
+                    (Some(alias), _) => {
+                        key = alias.name.to_ascii_lowercase();
+                        alias.env.clone()
+                    }
+                    (None, Some(parent)) => parent.clone(),
+                    (None, None) => return false,
+                }
+            };
+            env = next;
+        }
+    }
+
+    /// Bind name to a procedure implemented by a Rust closure
+    ///
+    /// The closure receives the evaluated arguments once their count matches
+    /// arity, and an error it returns propagates as a Lisp error.
+    pub fn define_native<F>(&mut self, name: impl AsRef<str>, arity: impl Into<Arity>, func: F)
+    where
+        F: Fn(Vec<Expr>) -> anyhow::Result<Expr> + 'static,
+    {
+        let native = Expr::Native {
+            name: name.as_ref().into(),
+            arity: arity.into(),
+            func: Rc::new(func),
+        };
+        self.set(name, native);
     }
 
     pub fn update(&mut self, data: Rc<RefCell<Self>>) {
@@ -43,5 +148,17 @@ impl Env {
                 .iter()
                 .map(|(k, v)| (k.clone(), v.clone())),
         );
+        self.macros.extend(
+            data.borrow()
+                .macros
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+        self.aliases.extend(
+            data.borrow()
+                .aliases
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
     }
 }
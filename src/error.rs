@@ -1 +1,219 @@
+use crate::lexer::Token;
+use crate::span::Span;
+use std::error::Error;
+use std::fmt;
 
+/// Errors raised while reading or evaluating a LISP program
+#[derive(Debug, Clone, PartialEq)]
+pub enum LispError {
+    /// Symbol has no binding in any enclosing Env
+    UnboundSymbol(String),
+    /// Callable received a wrong number of arguments
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Variadic callable received fewer than its required arguments
+    TooFewArguments {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Operand is not of the expected kind
+    TypeMismatch {
+        expected: &'static str,
+        found: String,
+    },
+    /// Integer division with zero divisor
+    DivisionByZero,
+    /// Missing Token::LParen or Token::RParen
+    UnbalancedParens,
+    /// Token is not allowed at this position
+    UnexpectedToken(Token),
+    /// Head of a call does not evaluate to a callable
+    NotCallable(String),
+    /// String literal without closing quote
+    UnterminatedString,
+    /// Unknown escape sequence in a string literal
+    InvalidEscape(String),
+    /// Block comment without closing `|#`
+    UnterminatedComment,
+    /// Special form used with a malformed shape
+    BadSyntax(String),
+    /// Input, datum comment or quote shorthand ended without a datum
+    MissingDatum,
+    /// Lexeme starts like a number but is not one
+    MalformedNumber(String),
+    /// Exact decimal whose exponent is beyond the supported magnitude
+    ExponentTooLarge(String),
+    /// Evaluation exceeded its limit of pending continuations
+    StackOverflow,
+}
+
+impl fmt::Display for LispError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnboundSymbol(sym) => write!(f, "symbol `{}` not defined", sym),
+            Self::ArityMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` expects {} argument(s), found {}",
+                name, expected, found
+            ),
+            Self::TooFewArguments {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` expects at least {} argument(s), found {}",
+                name, expected, found
+            ),
+            Self::TypeMismatch { expected, found } => {
+                write!(f, "expect {}, found `{}`", expected, found)
+            }
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::UnbalancedParens => write!(f, "unbalanced parentheses"),
+            Self::UnexpectedToken(token) => write!(f, "unexpected `{}`", token),
+            Self::NotCallable(expr) => write!(f, "`{}` is not callable", expr),
+            Self::UnterminatedString => write!(f, "unterminated string literal"),
+            Self::InvalidEscape(escape) => write!(f, "invalid escape sequence `{}`", escape),
+            Self::BadSyntax(reason) => write!(f, "bad syntax: {}", reason),
+            Self::UnterminatedComment => write!(f, "unterminated block comment"),
+            Self::MissingDatum => write!(f, "missing datum"),
+            Self::MalformedNumber(lexeme) => write!(f, "malformed number `{}`", lexeme),
+            Self::ExponentTooLarge(lexeme) => write!(f, "exponent too large in `{}`", lexeme),
+            Self::StackOverflow => write!(f, "stack overflow"),
+        }
+    }
+}
+
+impl Error for LispError {}
+
+impl LispError {
+    /// Convert into an error located at span
+    pub fn at(self, span: Span) -> anyhow::Error {
+        let diagnostic = Diagnostic::new(&self, span);
+        anyhow::Error::new(self).context(diagnostic)
+    }
+}
+
+/// Error message located at a Span, rendered with a caret under the offending form
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl fmt::Display, span: Span) -> Self {
+        Self {
+            message: message.to_string(),
+            span,
+        }
+    }
+
+    /// Locate err at span unless an inner form already located it
+    pub fn attach(err: anyhow::Error, span: &Span) -> anyhow::Error {
+        if err.is::<Diagnostic>() {
+            return err;
+        }
+        let diagnostic = Diagnostic::new(&err, span.clone());
+        err.context(diagnostic)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = &self.span;
+        let text = span.source.line(span.line);
+        let gutter = " ".repeat(span.line.to_string().len());
+
+        // Underline up to the end of the first line of a multi-line form
+        let offset = text
+            .char_indices()
+            .nth(span.column - 1)
+            .map_or(text.len(), |(i, _)| i);
+        let width = text[offset..]
+            .char_indices()
+            .take_while(|&(i, _)| i < span.range.len())
+            .count()
+            .max(1);
+
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter, span.source.name, span.line, span.column
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", span.line, text)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(span.column - 1),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl Error for Diagnostic {}
+
+/// Call of a user function active when an error was raised
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: String,
+    /// Printed values of the evaluated arguments
+    pub args: Vec<String>,
+    /// Span of the call form, if it was parsed from a Source
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}", self.name)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        write!(f, ")")?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}:{}:{}", span.source.name, span.line, span.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lisp call stack attached to an evaluation error, innermost Frame first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backtrace {
+    pub message: String,
+    pub frames: Vec<Frame>,
+}
+
+impl Backtrace {
+    /// Frames printed at each end of a longer Backtrace
+    pub const SHOWN: usize = 10;
+}
+
+/// Prints the innermost and outermost SHOWN frames, counting the ones between
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\nbacktrace:", self.message)?;
+        let elided = self.frames.len().saturating_sub(2 * Self::SHOWN);
+        for (i, frame) in self.frames.iter().enumerate() {
+            match i.checked_sub(Self::SHOWN) {
+                Some(0) if elided > 0 => write!(f, "\n      ... {} frames elided", elided)?,
+                Some(n) if n < elided => (),
+                _ => write!(f, "\n{:>4}: {}", i, frame)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for Backtrace {}
@@ -1,135 +1,1285 @@
 use crate::env::Env;
 use crate::expr::Expr;
+use crate::lexer::Lexer;
 use crate::parser::Parser;
+use crate::span::Source;
 use std::cell::RefCell;
 use std::path::Path;
 use std::rc::Rc;
+use std::sync::Arc;
+
+pub use eval_state::Continuation;
+pub use eval_state::Control;
 
 pub struct Evaluator;
 
 impl Evaluator {
+    /// Most continuation frames an evaluation may have pending
+    pub const DEFAULT_STACK_LIMIT: usize = 1_000_000;
+
     pub fn eval_file(path: impl AsRef<Path>, env: &mut Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
-        let expr = Parser::parse_file(path)?;
-        eval_state::eval_expr(expr, env)
+        Self::eval_source(Lexer::read_file(path)?, env)
     }
 
     pub fn eval(source: impl AsRef<str>, env: &mut Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
-        let expr = Parser::parse(source)?;
-        eval_state::eval_expr(expr, env)
+        Self::eval_source(Source::new("<input>", source.as_ref()), env)
+    }
+
+    /// Evaluate every form of a named Source in order, returning the last value
+    ///
+    /// Errors are located with a Diagnostic
+    pub fn eval_source(source: Arc<Source>, env: &mut Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
+        Self::eval_source_limited(source, env, Self::DEFAULT_STACK_LIMIT)
+    }
+
+    /// Like eval_source, failing with StackOverflow once more than limit
+    /// continuation frames are pending
+    pub fn eval_source_limited(
+        source: Arc<Source>,
+        env: &mut Rc<RefCell<Env>>,
+        limit: usize,
+    ) -> anyhow::Result<Expr> {
+        let (exprs, spans) = Parser::parse_spanned(source)?;
+        let mut state = eval_state::State::new(spans, limit);
+        eval_state::eval_program(exprs, env, &mut state).map_err(|err| state.backtrace(err))
     }
 }
 
+/// Evaluation as a machine over a heap-allocated stack of continuations
+///
+/// A form either yields a value or pushes the work left after one of its
+/// subforms and continues with that subform. A value is handed to the
+/// innermost pending Cont. Forms in tail position push nothing, so tail calls
+/// run in constant space and recursion depth is bounded only by the limit.
 mod eval_state {
     use super::{Env, Expr, Rc, RefCell};
-    use crate::{builtins::*, consts::*, intrinsics::*, math::*, Token};
+    use crate::error::{Backtrace, Diagnostic, Frame, LispError};
+    use crate::expr::{Arity, ExprField, NativeFn};
+    use crate::macros::{Alias, Macro, Transformer};
+    use crate::span::{Span, SpanTable};
+    use crate::{builtins::*, consts::*, intrinsics::*, Token};
+    use std::fmt;
+    use std::hash::{Hash, Hasher};
 
-    pub fn eval_expr(expr: Expr, env: &mut Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
-        match expr {
-            Expr::Atom(Token::Symbol(_)) => eval_symbol(expr, env),
-            Expr::Atom(_) => Ok(expr),
-            _ => match left_most(expr.clone()) {
-                atom @ Expr::Atom(_) if is_unary(&atom) => eval_unary(atom, cdr(expr), env),
-                atom @ Expr::Atom(_) if is_binary(&atom) => {
-                    eval_binary(atom, cdr(car(expr.clone())), cdr(expr), env)
-                }
-                Expr::Atom(Token::Apply) => eval_apply(expr, env),
-                Expr::Atom(Token::Define) => eval_define(cdr(car(expr.clone())), cdr(expr), env),
-                Expr::Atom(Token::Cond) => eval_cond(expr, env),
-                _ => Ok(NIL),
-            },
+    /// Bookkeeping shared by all eval functions during one evaluation
+    pub struct State {
+        pub spans: SpanTable,
+        /// Most continuations that may be pending at once
+        pub limit: usize,
+        /// Snapshot of frames where the pending error was raised
+        pub trace: Option<Vec<Frame>>,
+        /// Top-level forms being evaluated, as a body list
+        program: Expr,
+    }
+
+    impl State {
+        pub fn new(spans: SpanTable, limit: usize) -> Self {
+            Self {
+                spans,
+                limit,
+                trace: None,
+                program: NIL,
+            }
+        }
+
+        /// Attach the Lisp call stack captured for err, if any
+        pub fn backtrace(&mut self, err: anyhow::Error) -> anyhow::Error {
+            match self.trace.take() {
+                Some(frames) if !frames.is_empty() => {
+                    let message = err.to_string();
+                    err.context(Backtrace {
+                        message,
+                        frames: frames.into_iter().rev().collect(),
+                    })
+                }
+                _ => err,
+            }
         }
     }
 
-    pub fn eval_symbol(expr: Expr, env: &mut Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
-        match expr {
-            Expr::Atom(Token::Symbol(ref sym)) => match env.borrow().get(sym) {
-                Some(val) => return Ok(val),
-                _ => panic!("Symbol `{}` not defined", sym),
-            },
-            _ => panic!("Internal error"),
+    /// What the machine does next
+    enum Mode {
+        /// Evaluate a form in an Env
+        Eval(Expr, Rc<RefCell<Env>>),
+        /// Hand a value to the innermost Cont
+        Return(Expr),
+    }
+
+    /// Work left once the value of a subform is known
+    ///
+    /// Lists of pending forms are stored reversed, next form last.
+    #[derive(Clone)]
+    enum Cont {
+        /// Evaluate the rest of a body after its form in the first cell of
+        /// forms, discarding the value
+        Body {
+            forms: Expr,
+            env: Rc<RefCell<Env>>,
+        },
+        /// Collect the callee and arguments of form, then call
+        Call {
+            form: Expr,
+            name: String,
+            pending: Vec<Expr>,
+            values: Vec<Expr>,
+            env: Rc<RefCell<Env>>,
+        },
+        /// Run body if the clause test held, else test the next clause
+        Cond {
+            form: Expr,
+            body: Expr,
+            clauses: Vec<Expr>,
+            env: Rc<RefCell<Env>>,
+        },
+        If {
+            then: Expr,
+            otherwise: Expr,
+            env: Rc<RefCell<Env>>,
+        },
+        /// Bind name to the value of its init, then go on with bindings
+        Let {
+            form: Expr,
+            name: String,
+            bindings: Bindings,
+        },
+        Define {
+            name: String,
+            env: Rc<RefCell<Env>>,
+        },
+        Set {
+            form: Expr,
+            name: String,
+            env: Rc<RefCell<Env>>,
+        },
+        /// Evaluate the value once more, the expansion of the macro use form
+        Eval {
+            form: Expr,
+            env: Rc<RefCell<Env>>,
+        },
+        /// Pass on the value of the expansion of the macro use form, which
+        /// locates errors in code the expansion made up
+        Expanded {
+            form: Expr,
+        },
+        /// Expand the value if it is a macro use, for `macroexpand-1`, and
+        /// again until it is not one if repeat is set, for `macroexpand`
+        Expand {
+            env: Rc<RefCell<Env>>,
+            repeat: bool,
+        },
+        /// Body of the closure call
+        Return(Call),
+    }
+
+    /// Pending closure or macro call, kept unformatted until an error needs
+    /// its Frame
+    #[derive(Clone)]
+    struct Call {
+        name: String,
+        args: Vec<Expr>,
+        span: Option<Span>,
+        /// Body list of the callee, its last form in tail position
+        body: Expr,
+        /// Span of the macro use whose expansion is in tail position
+        expansion: Option<Span>,
+    }
+
+    impl Call {
+        fn frame(&self) -> Frame {
+            Frame {
+                name: self.name.clone(),
+                args: self.args.iter().map(Expr::to_string).collect(),
+                span: self.span.clone(),
+            }
         }
     }
 
-    pub fn eval_unary(op: Expr, expr: Expr, env: &mut Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
-        match op {
-            Expr::Atom(Token::Symbol(ref sym)) => match sym.as_str() {
-                "car" => return Ok(car(eval_expr(expr, env)?)),
-                "cdr" => return Ok(cdr(eval_expr(expr, env)?)),
-                "atom" => return Ok(atom(eval_expr(expr, env)?)),
-                "null" => return Ok(null(eval_expr(expr, env)?)),
-                "quote" => return Ok(quote(expr)),
-                "eval" => return Ok(eval(eval_expr(eval_expr(expr, env)?, env)?)),
-                _ => panic!("Bad Token::Symbol({})", sym),
-            },
-            _ => panic!("Expect Token::Symbol"),
+    /// Rest of an evaluation captured by `call/cc`
+    ///
+    /// Calling it with a value abandons the current continuation and returns
+    /// the value to the captured one instead, which may be resumed any number
+    /// of times.
+    #[derive(Clone)]
+    pub struct Continuation {
+        stack: Rc<Vec<Cont>>,
+    }
+
+    /// Continuations are equal only if they are the same capture
+    impl PartialEq for Continuation {
+        fn eq(&self, other: &Self) -> bool {
+            Rc::ptr_eq(&self.stack, &other.stack)
         }
     }
 
-    pub fn eval_binary(
-        op: Expr,
-        lhs: Expr,
-        rhs: Expr,
+    impl Eq for Continuation {}
+
+    impl Hash for Continuation {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            Rc::as_ptr(&self.stack).hash(state);
+        }
+    }
+
+    /// Procedure acting on the evaluation itself rather than on its arguments
+    /// alone, bound like any other value
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub enum Control {
+        /// `call/cc`, calling its receiver with the current continuation
+        CallCc,
+        /// `eval`, evaluating its argument in the Env of the call
+        Eval,
+        /// `macroexpand-1`, expanding a macro use once
+        MacroExpand1,
+        /// `macroexpand`, expanding a macro use until it is not one
+        MacroExpand,
+    }
+
+    impl Control {
+        /// Names every Control is bound to in a new Env
+        pub const BINDINGS: &[(&str, Control)] = &[
+            ("call/cc", Control::CallCc),
+            ("call-with-current-continuation", Control::CallCc),
+            ("eval", Control::Eval),
+            ("macroexpand-1", Control::MacroExpand1),
+            ("macroexpand", Control::MacroExpand),
+        ];
+
+        pub fn name(self) -> &'static str {
+            match self {
+                Control::CallCc => "call/cc",
+                Control::Eval => "eval",
+                Control::MacroExpand1 => "macroexpand-1",
+                Control::MacroExpand => "macroexpand",
+            }
+        }
+    }
+
+    impl fmt::Debug for Continuation {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Continuation")
+                .field("depth", &self.stack.len())
+                .finish()
+        }
+    }
+
+    impl Cont {
+        /// Form this Cont is part of, to locate errors
+        fn form(&self) -> Option<&Expr> {
+            match self {
+                Self::Call { form, .. }
+                | Self::Cond { form, .. }
+                | Self::Eval { form, .. }
+                | Self::Expanded { form }
+                | Self::Let { form, .. }
+                | Self::Set { form, .. } => Some(form),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum LetKind {
+        Let,
+        /// `(let loop ...)`, calling a procedure over the bindings
+        Named,
+        LetStar,
+        Letrec,
+    }
+
+    /// Progress through the bindings of a let form
+    #[derive(Clone)]
+    struct Bindings {
+        kind: LetKind,
+        /// Procedure name of a named let
+        name: String,
+        pending: Vec<(String, Expr)>,
+        /// Values of let and named let, bound together once all are known
+        bound: Vec<(String, Expr)>,
+        body: Expr,
+        env: Rc<RefCell<Env>>,
+        /// Env the next init is evaluated in
+        scope: Rc<RefCell<Env>>,
+    }
+
+    /// Evaluate the forms of a program in env in order, returning the last
+    /// value
+    ///
+    /// The forms run as one body, so a continuation captured by one of them
+    /// also runs the forms after it.
+    pub fn eval_program(
+        exprs: Vec<Expr>,
         env: &mut Rc<RefCell<Env>>,
+        state: &mut State,
     ) -> anyhow::Result<Expr> {
-        let lhs = eval_expr(lhs, env)?;
-        let rhs = eval_expr(rhs, env)?;
-
-        match op {
-            Expr::Atom(Token::Symbol(ref sym)) => match sym.as_str() {
-                "cons" => return Ok(cons(lhs, rhs)),
-                "eq" => return Ok(eq(lhs, rhs)),
-                "add" | "+" => return Ok(add(lhs, rhs)),
-                "sub" | "-" => return Ok(sub(lhs, rhs)),
-                "mul" | "*" => return Ok(mul(lhs, rhs)),
-                "div" | "/" => return Ok(div(lhs, rhs)),
-                _ => panic!("Bad Token::Symbol({})", sym),
-            },
-            Expr::Atom(Token::Lambda) => Ok(NIL),
-            _ => panic!("Expect Token::Symbol | Token::Lambda"),
+        let program = list(exprs);
+        let forms = state.spans.forms().to_vec();
+        state.spans.insert_elements(&program, forms);
+        state.program = program.clone();
+
+        let mut stack = Vec::new();
+        let mut mode = eval_body(program, env.clone(), &mut stack);
+
+        loop {
+            let (step, form) = match mode {
+                Mode::Eval(expr, _) if stack.len() > state.limit => {
+                    (Err(LispError::StackOverflow.into()), Some(expr))
+                }
+                Mode::Eval(expr, env) => {
+                    (eval_step(expr.clone(), env, &mut stack, state), Some(expr))
+                }
+                Mode::Return(value) => match stack.pop() {
+                    None => return Ok(value),
+                    Some(cont) => {
+                        let form = cont.form().cloned();
+                        (resume(cont, value, &mut stack, state), form)
+                    }
+                },
+            };
+            mode = step.map_err(|err| fail(err, form.as_ref(), &stack, state))?;
         }
     }
 
-    pub fn eval_apply(expr: Expr, env: &mut Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
-        let exprs = collect(expr);
+    /// Locate err at form, or else the innermost pending form or call with a
+    /// Span, and capture the closure calls on stack
+    ///
+    /// An atom is looked up within the forms pending in the innermost call,
+    /// since equal atoms elsewhere may have other Spans.
+    fn fail(
+        err: anyhow::Error,
+        form: Option<&Expr>,
+        stack: &[Cont],
+        state: &mut State,
+    ) -> anyhow::Error {
+        state.trace.get_or_insert_with(|| {
+            stack
+                .iter()
+                .filter_map(|cont| match cont {
+                    Cont::Return(call) => Some(call.frame()),
+                    _ => None,
+                })
+                .collect()
+        });
 
-        match exprs[0] {
-            Expr::Atom(Token::Symbol(ref sym)) => match env.clone().borrow().get(sym) {
-                Some(lambda) => {
-                    let mut new_env = Rc::new(RefCell::new(Env::extend(env.clone())));
-                    let args = flatten(cdr(car(lambda.clone())));
-                    for iarg in 0..args.len() {
-                        match args[iarg] {
-                            Expr::Atom(Token::Symbol(ref arg_sym)) => new_env
-                                .borrow_mut()
-                                .set(arg_sym, eval_expr(exprs[iarg + 1].clone(), env)?),
-                            _ => panic!("Lambda argument is not Token::Symbol"),
+        let pending = stack.iter().rev().find_map(|cont| match cont {
+            Cont::Return(call) => call.expansion.as_ref().or(call.span.as_ref()),
+            _ => cont.form().and_then(|form| state.spans.get(form)),
+        });
+        let located = match form {
+            Some(atom @ Expr::Atom(_)) => locate(atom, stack, state),
+            Some(form) => state.spans.get(form),
+            None => None,
+        };
+        match located.or(pending) {
+            Some(span) => Diagnostic::attach(err, span),
+            None => err,
+        }
+    }
+
+    /// Span of atom within the innermost form holding it
+    ///
+    /// Atoms are searched in the pending forms up to the form of the
+    /// enclosing body being evaluated, which is the last one of a call body
+    /// under its Return, or of the program once the stack runs out.
+    fn locate<'a>(atom: &Expr, stack: &[Cont], state: &'a State) -> Option<&'a Span> {
+        let spans = &state.spans;
+        for cont in stack.iter().rev() {
+            match cont {
+                Cont::Body { forms, .. } => return spans.find_element(atom, forms),
+                Cont::Return(call) => return spans.find_element(atom, last_cell(&call.body)),
+                _ => {
+                    if let Some(span) = cont.form().and_then(|form| spans.find(atom, form)) {
+                        return Some(span);
+                    }
+                }
+            }
+        }
+        spans.find_element(atom, last_cell(&state.program))
+    }
+
+    /// Last cell of a list, holding the form in tail position of a body
+    fn last_cell(list: &Expr) -> &Expr {
+        let mut last = list;
+        while let Expr::Composed { cdr, .. } = last {
+            match cdr.as_ref() {
+                Expr::Composed { .. } => last = cdr,
+                _ => break,
+            }
+        }
+        last
+    }
+
+    fn eval_step(
+        expr: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+        state: &State,
+    ) -> anyhow::Result<Mode> {
+        let (car, cdr) = match &expr {
+            Expr::Atom(Token::Symbol(_)) => return eval_symbol(expr, &env).map(Mode::Return),
+            Expr::Atom(_)
+            | Expr::Closure { .. }
+            | Expr::Native { .. }
+            | Expr::Continuation(_)
+            | Expr::Control(_) => return Ok(Mode::Return(expr)),
+            Expr::Composed { car, cdr } => (car.as_ref().clone(), cdr.as_ref().clone()),
+        };
+
+        // A macro use evaluates to its expansion, evaluated in place
+        let car = keyword(car, &env);
+        if let Some(transformer) = macro_use(&car, &env) {
+            let call_site = state.spans.get(&expr).cloned();
+            stack.push(Cont::Eval {
+                form: expr.clone(),
+                env: env.clone(),
+            });
+            return expand(transformer, cdr, &env, call_site, stack);
+        }
+
+        match car {
+            Expr::Atom(Token::Symbol(ref sym)) if is_special(sym) => {
+                eval_special(sym, expr, cdr, env, stack)
+            }
+            Expr::Atom(Token::Apply) => eval_apply(expr, cdr, env, stack),
+            Expr::Atom(Token::Lambda) => eval_lambda(cdr, &env).map(Mode::Return),
+            Expr::Atom(Token::Define) => eval_define(cdr, env, stack),
+            Expr::Atom(Token::Set) => eval_set(expr, cdr, env, stack),
+            Expr::Atom(Token::Cond) => eval_cond(expr, cdr, env, stack),
+            _ => eval_call(expr.clone(), expr, env, stack),
+        }
+    }
+
+    /// Go on with cont now that its subform evaluated to value
+    fn resume(
+        cont: Cont,
+        value: Expr,
+        stack: &mut Vec<Cont>,
+        state: &mut State,
+    ) -> anyhow::Result<Mode> {
+        match cont {
+            Cont::Body { forms, env } => match cdr(forms) {
+                rest @ Expr::Composed { .. } => Ok(eval_body(rest, env, stack)),
+                _ => Ok(Mode::Return(value)),
+            },
+            Cont::Call {
+                form,
+                name,
+                mut pending,
+                mut values,
+                env,
+            } => {
+                values.push(value);
+                match pending.pop() {
+                    Some(expr) => {
+                        stack.push(Cont::Call {
+                            form,
+                            name,
+                            pending,
+                            values,
+                            env: env.clone(),
+                        });
+                        Ok(Mode::Eval(expr, env))
+                    }
+                    None => {
+                        let callee = values.remove(0);
+                        let call_site = state.spans.get(&form).cloned();
+                        apply(name, callee, values, call_site, &env, stack)
+                    }
+                }
+            }
+            Cont::Cond {
+                form,
+                body,
+                clauses,
+                env,
+            } => match value {
+                Expr::Atom(Token::True) => Ok(eval_body(body, env, stack)),
+                _ => next_clause(form, clauses, env, stack),
+            },
+            Cont::If {
+                then,
+                otherwise,
+                env,
+            } => match value {
+                Expr::Atom(Token::True) => Ok(Mode::Eval(then, env)),
+                _ => Ok(Mode::Eval(otherwise, env)),
+            },
+            Cont::Let {
+                form,
+                name,
+                mut bindings,
+            } => {
+                match bindings.kind {
+                    LetKind::Let | LetKind::Named => bindings.bound.push((name, value)),
+                    LetKind::LetStar => {
+                        let scope = Rc::new(RefCell::new(Env::extend(bindings.scope)));
+                        scope.borrow_mut().set(name, value);
+                        bindings.scope = scope;
+                    }
+                    LetKind::Letrec => bindings.scope.borrow_mut().set(name, value),
+                }
+                next_binding(form, bindings, stack)
+            }
+            Cont::Define { name, env } => {
+                env.borrow_mut().set(name, value);
+                Ok(Mode::Return(NIL))
+            }
+            Cont::Set { name, env, .. } => {
+                if Env::assign(&env, &name, value) {
+                    Ok(Mode::Return(NIL))
+                } else {
+                    Err(LispError::UnboundSymbol(Alias::base(&name).into()).into())
+                }
+            }
+            Cont::Eval { form, env } => {
+                // In tail position the Return stays on top, so tail calls
+                // in the expansion still run in constant space
+                match stack.last_mut() {
+                    Some(Cont::Return(call)) => {
+                        if let Some(span) = state.spans.get(&form) {
+                            call.expansion = Some(span.clone());
                         }
                     }
-                    return eval_expr(cdr(lambda), &mut new_env);
+                    _ => stack.push(Cont::Expanded { form }),
+                }
+                Ok(Mode::Eval(eval(value), env))
+            }
+            Cont::Expanded { .. } => Ok(Mode::Return(value)),
+            Cont::Expand { env, repeat } => {
+                let transformer = match macro_use(&keyword(car(value.clone()), &env), &env) {
+                    Some(transformer) => transformer,
+                    None => return Ok(Mode::Return(value)),
+                };
+                if repeat {
+                    stack.push(Cont::Expand {
+                        env: env.clone(),
+                        repeat,
+                    });
+                }
+                expand(transformer, cdr(value), &env, None, stack)
+            }
+            Cont::Return(_) => Ok(Mode::Return(value)),
+        }
+    }
+
+    /// Elements of an argument list, checking there are exactly N of them
+    fn arguments<const N: usize>(name: &str, args: Expr) -> anyhow::Result<[Expr; N]> {
+        let args = elements(args)?;
+        let found = args.len();
+        args.try_into().map_err(|_| {
+            LispError::ArityMismatch {
+                name: name.into(),
+                expected: N,
+                found,
+            }
+            .into()
+        })
+    }
+
+    /// Evaluate a body list in env, leaving its last form in tail position
+    fn eval_body(body: Expr, env: Rc<RefCell<Env>>, stack: &mut Vec<Cont>) -> Mode {
+        let (expr, last) = match &body {
+            Expr::Composed { car, cdr } => (car.as_ref().clone(), **cdr == NIL),
+            _ => return Mode::Return(NIL),
+        };
+        if !last {
+            stack.push(Cont::Body {
+                forms: body,
+                env: env.clone(),
+            });
+        }
+        Mode::Eval(expr, env)
+    }
+
+    pub fn eval_symbol(expr: Expr, env: &Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
+        match expr {
+            Expr::Atom(Token::Symbol(ref sym)) => env
+                .borrow()
+                .get(sym)
+                .ok_or_else(|| LispError::UnboundSymbol(Alias::base(sym).into()).into()),
+            _ => Err(LispError::TypeMismatch {
+                expected: "Token::Symbol",
+                found: expr.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// Forms that receive their arguments unevaluated, shadowing any binding
+    fn is_special(sym: &str) -> bool {
+        matches!(
+            sym,
+            "quote"
+                | "quasiquote"
+                | "if"
+                | "let"
+                | "let*"
+                | "letrec"
+                | "defmacro"
+                | "define-syntax"
+                | "let-syntax"
+        )
+    }
+
+    fn eval_special(
+        sym: &str,
+        form: Expr,
+        args: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        match sym {
+            "if" => return eval_if(args, env, stack),
+            "let" => return eval_let(form, args, env, stack),
+            "let*" => return eval_let_with(LetKind::LetStar, form, args, env, stack),
+            "letrec" => return eval_let_with(LetKind::Letrec, form, args, env, stack),
+            "defmacro" => return eval_defmacro(args, &env).map(Mode::Return),
+            "define-syntax" => return eval_define_syntax(args, &env).map(Mode::Return),
+            "let-syntax" => return eval_let_syntax(args, env, stack),
+            _ => (),
+        }
+
+        let [expr] = arguments(sym, args)?;
+        match sym {
+            "quote" => Ok(Mode::Return(quote(expr))),
+            "quasiquote" => Ok(Mode::Eval(quasiquote(expr, 1)?, env)),
+            _ => Err(LispError::NotCallable(sym.into()).into()),
+        }
+    }
+
+    /// `(if test then else)` evaluates then if test is TRUE, else otherwise
+    ///
+    /// A missing else branch yields NIL.
+    fn eval_if(args: Expr, env: Rc<RefCell<Env>>, stack: &mut Vec<Cont>) -> anyhow::Result<Mode> {
+        let (test, then, otherwise) = match elements(args)?.as_slice() {
+            [test, then] => (test.clone(), then.clone(), NIL),
+            [test, then, otherwise] => (test.clone(), then.clone(), otherwise.clone()),
+            _ => return Err(LispError::BadSyntax("if without test and branches".into()).into()),
+        };
+
+        stack.push(Cont::If {
+            then,
+            otherwise,
+            env: env.clone(),
+        });
+        Ok(Mode::Eval(test, env))
+    }
+
+    /// Names and init expressions of a `((name init)...)` binding list
+    fn bindings(form: &str, bindings: Expr) -> anyhow::Result<Vec<(String, Expr)>> {
+        elements(bindings)?
+            .into_iter()
+            .map(|binding| match elements(binding.clone())?.as_slice() {
+                [Expr::Atom(Token::Symbol(name)), init] => Ok((name.clone(), init.clone())),
+                _ => Err(LispError::BadSyntax(format!("{} binding {}", form, binding)).into()),
+            })
+            .collect()
+    }
+
+    /// Binding list and body list of a let form, requiring at least one body
+    /// expression
+    fn let_parts(form: &str, args: Expr) -> anyhow::Result<(Expr, Expr)> {
+        match elements(args.clone())?.len() {
+            2.. => Ok((car(args.clone()), cdr(args))),
+            _ => Err(LispError::BadSyntax(format!("{} without body", form)).into()),
+        }
+    }
+
+    /// `(let ((name init)...) body...)` binds the inits, evaluated in env, in a
+    /// new frame
+    ///
+    /// Named `(let loop ((name init)...) body...)` also binds loop in the body
+    /// to a procedure taking the names and running the body.
+    fn eval_let(
+        form: Expr,
+        args: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        match car(args.clone()) {
+            Expr::Atom(Token::Symbol(_)) => eval_let_with(LetKind::Named, form, args, env, stack),
+            _ => eval_let_with(LetKind::Let, form, args, env, stack),
+        }
+    }
+
+    /// Start evaluating the inits of a let form of kind
+    ///
+    /// `(let* ((name init)...) body...)` evaluates each init with the earlier
+    /// names already bound. `(letrec ((name init)...) body...)` evaluates every
+    /// init with all names bound, so inits can be mutually recursive lambdas.
+    fn eval_let_with(
+        kind: LetKind,
+        form: Expr,
+        args: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        let keyword = match kind {
+            LetKind::Let | LetKind::Named => "let",
+            LetKind::LetStar => "let*",
+            LetKind::Letrec => "letrec",
+        };
+        let (name, args) = match kind {
+            LetKind::Named => match car(args.clone()) {
+                Expr::Atom(Token::Symbol(name)) => (name, cdr(args)),
+                _ => (String::new(), cdr(args)),
+            },
+            _ => (String::new(), args),
+        };
+        let (pending, body) = let_parts(keyword, args)?;
+        let mut pending = bindings(keyword, pending)?;
+        pending.reverse();
+
+        let scope = match kind {
+            LetKind::Letrec => {
+                let scope = Rc::new(RefCell::new(Env::extend(env.clone())));
+                for (name, _) in &pending {
+                    scope.borrow_mut().set(name, NIL);
+                }
+                scope
+            }
+            _ => env.clone(),
+        };
+        let bindings = Bindings {
+            kind,
+            name,
+            pending,
+            bound: Vec::new(),
+            body,
+            env,
+            scope,
+        };
+        next_binding(form, bindings, stack)
+    }
+
+    /// Evaluate the next init of bindings, or the body once all are bound
+    fn next_binding(
+        form: Expr,
+        mut bindings: Bindings,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        if let Some((name, init)) = bindings.pending.pop() {
+            let scope = bindings.scope.clone();
+            stack.push(Cont::Let {
+                form,
+                name,
+                bindings,
+            });
+            return Ok(Mode::Eval(init, scope));
+        }
+
+        let Bindings {
+            kind,
+            name,
+            bound,
+            body,
+            env,
+            scope,
+            ..
+        } = bindings;
+        match kind {
+            LetKind::Let => {
+                let new_env = Rc::new(RefCell::new(Env::extend(env)));
+                for (name, value) in bound {
+                    new_env.borrow_mut().set(name, value);
+                }
+                Ok(eval_body(body, new_env, stack))
+            }
+            LetKind::Named => {
+                let (params, values) = bound.into_iter().unzip();
+                let procedure = Expr::Closure {
+                    params,
+                    body: ExprField::new(body),
+                    env,
+                    recur: Some(name.clone()),
+                };
+                apply(name, procedure, values, None, &scope, stack)
+            }
+            LetKind::LetStar => {
+                let new_env = Rc::new(RefCell::new(Env::extend(scope)));
+                Ok(eval_body(body, new_env, stack))
+            }
+            LetKind::Letrec => Ok(eval_body(body, scope, stack)),
+        }
+    }
+
+    /// Code building a quasiquote template, evaluating unquotes at nesting depth 1
+    ///
+    /// Templates are expanded into calls of unnamed natives, which user
+    /// bindings cannot shadow.
+    pub fn quasiquote(template: Expr, depth: usize) -> anyhow::Result<Expr> {
+        /// Step of building the code for a template
+        enum Task {
+            /// Push the code for a template at a nesting depth
+            Expand(Expr, usize),
+            /// Wrap the code on top in `(keyword datum)`
+            Wrap(Expr),
+            /// Build a list from the code of its tail and of each element
+            /// not spliced, found on top in order; spliced elements hold the
+            /// form to splice
+            List(Vec<Option<Expr>>),
+        }
+
+        // Nested templates are expanded without recursion
+        let mut tasks = vec![Task::Expand(template, depth)];
+        let mut code = Vec::new();
+        while let Some(task) = tasks.pop() {
+            let (template, depth) = match task {
+                Task::Expand(template, depth) => (template, depth),
+                Task::Wrap(keyword) => {
+                    let datum = code.pop().unwrap_or(NIL);
+                    code.push(list(vec![
+                        splicer("cons"),
+                        keyword,
+                        list(vec![splicer("cons"), datum, quoted(NIL)]),
+                    ]));
+                    continue;
+                }
+                Task::List(elements) => {
+                    let mut parts = code.split_off(
+                        code.len() - elements.iter().filter(|element| element.is_none()).count(),
+                    );
+                    let mut list_code = code.pop().unwrap_or(NIL);
+                    for element in elements.into_iter().rev() {
+                        list_code = match element {
+                            Some(datum) => list(vec![splicer("append"), datum, list_code]),
+                            None => {
+                                let element = parts.pop().unwrap_or(NIL);
+                                list(vec![splicer("cons"), element, list_code])
+                            }
+                        };
+                    }
+                    code.push(list_code);
+                    continue;
+                }
+            };
+
+            if let Expr::Atom(_) = template {
+                code.push(quoted(template));
+                continue;
+            }
+
+            match quasi_form(&template) {
+                Some(("unquote", datum)) if depth == 1 => {
+                    code.push(datum);
+                    continue;
                 }
-                _ => panic!("Callable symbol `{}` not defined", sym),
+                Some(("unquote-splicing", _)) if depth == 1 => {
+                    return Err(
+                        LispError::BadSyntax("unquote-splicing outside of a list".into()).into(),
+                    )
+                }
+                Some((keyword, datum)) => {
+                    let depth = if keyword == "quasiquote" {
+                        depth + 1
+                    } else {
+                        depth - 1
+                    };
+                    tasks.push(Task::Wrap(quoted(car(template))));
+                    tasks.push(Task::Expand(datum, depth));
+                    continue;
+                }
+                None => (),
+            }
+
+            let mut exprs = Vec::new();
+            let mut rest = template;
+            while let Expr::Composed { .. } = rest {
+                // `(a . ,b)` reads as `(a unquote b)`, leaving the unquote in the tail
+                if quasi_form(&rest).is_some() {
+                    break;
+                }
+                exprs.push(car(rest.clone()));
+                rest = cdr(rest);
+            }
+
+            // The tail is expanded first, then each element in order
+            let mut elements = Vec::new();
+            let mut expand = Vec::new();
+            for element in exprs {
+                match quasi_form(&element) {
+                    Some(("unquote-splicing", datum)) if depth == 1 => elements.push(Some(datum)),
+                    _ => {
+                        elements.push(None);
+                        expand.push(Task::Expand(element, depth));
+                    }
+                }
+            }
+            tasks.push(Task::List(elements));
+            tasks.extend(expand.into_iter().rev());
+            tasks.push(Task::Expand(rest, depth));
+        }
+        Ok(code.pop().unwrap_or(NIL))
+    }
+
+    /// Keyword and datum of a `(quasiquote x)`, `(unquote x)` or `(unquote-splicing x)` form
+    fn quasi_form(expr: &Expr) -> Option<(&'static str, Expr)> {
+        let keyword = match car(expr.clone()) {
+            Expr::Atom(Token::Symbol(sym)) => match sym.as_str() {
+                "quasiquote" => "quasiquote",
+                "unquote" => "unquote",
+                "unquote-splicing" => "unquote-splicing",
+                _ => return None,
             },
-            _ => panic!("Expect Token::Symbol"),
+            _ => return None,
+        };
+
+        match cdr(expr.clone()) {
+            Expr::Composed { car, cdr } if *cdr == NIL => Some((keyword, car.into_inner())),
+            _ => None,
         }
     }
 
-    pub fn eval_define(name: Expr, expr: Expr, env: &mut Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
+    /// `(quote expr)`
+    fn quoted(expr: Expr) -> Expr {
+        list(vec![Expr::Atom(Token::Symbol("quote".into())), expr])
+    }
+
+    /// Native used by quasiquote code: `cons`, or `append` of a spliced
+    /// proper list onto the rest of the template
+    fn splicer(name: &str) -> Expr {
+        let func: NativeFn = match name {
+            "cons" => Rc::new(|args| Ok(cons(args[0].clone(), args[1].clone()))),
+            _ => Rc::new(|args| Ok(dotted_list(elements(args[0].clone())?, args[1].clone()))),
+        };
+        Expr::Native {
+            name: name.into(),
+            arity: Arity::Exact(2),
+            func,
+        }
+    }
+
+    /// `(apply f args...)` calls f like the direct call `(f args...)`
+    fn eval_apply(
+        form: Expr,
+        call: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        match call {
+            Expr::Atom(Token::Nil) => Err(LispError::NotCallable(APPLY.to_string()).into()),
+            call => eval_call(form, call, env, stack),
+        }
+    }
+
+    /// Evaluate the head and arguments of `(f args...)`, then call f
+    ///
+    /// form is the whole call, located in errors and backtraces.
+    fn eval_call(
+        form: Expr,
+        call: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        let mut pending = elements(call)?;
+        pending.reverse();
+
+        let head = pending.pop().unwrap_or(NIL);
+        stack.push(Cont::Call {
+            form,
+            name: head.to_string(),
+            pending,
+            values: Vec::new(),
+            env: env.clone(),
+        });
+        Ok(Mode::Eval(head, env))
+    }
+
+    /// Call callee with evaluated args
+    ///
+    /// A closure body runs under a Return holding its Call. A tail
+    /// call finds the Return of its caller on top and replaces it. A Control
+    /// works in scope, the Env of the call.
+    fn apply(
+        name: String,
+        callee: Expr,
+        args: Vec<Expr>,
+        call_site: Option<Span>,
+        scope: &Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        let recur = match &callee {
+            Expr::Closure {
+                recur: Some(recur), ..
+            } => Some((recur.clone(), callee.clone())),
+            _ => None,
+        };
+        let (params, body, env) = match callee {
+            Expr::Native { .. } => return apply_native(callee, args).map(Mode::Return),
+            Expr::Continuation(continuation) => {
+                Arity::Exact(1).check(&name, args.len())?;
+                *stack = continuation.stack.as_ref().clone();
+                return Ok(Mode::Return(args.into_iter().next().unwrap_or(NIL)));
+            }
+            Expr::Control(control) => {
+                Arity::Exact(1).check(&name, args.len())?;
+                let arg = args.into_iter().next().unwrap_or(NIL);
+                return match control {
+                    Control::CallCc => {
+                        let continuation = Continuation {
+                            stack: Rc::new(stack.clone()),
+                        };
+                        let args = vec![Expr::Continuation(continuation)];
+                        apply(arg.to_string(), arg, args, call_site, scope, stack)
+                    }
+                    Control::Eval => Ok(Mode::Eval(eval(arg), scope.clone())),
+                    Control::MacroExpand1 | Control::MacroExpand => {
+                        stack.push(Cont::Expand {
+                            env: scope.clone(),
+                            repeat: control == Control::MacroExpand,
+                        });
+                        Ok(Mode::Return(arg))
+                    }
+                };
+            }
+            Expr::Closure {
+                params, body, env, ..
+            } => (params, body, env),
+            _ => return Err(LispError::NotCallable(callee.to_string()).into()),
+        };
+        if params.len() != args.len() {
+            return Err(LispError::ArityMismatch {
+                name,
+                expected: params.len(),
+                found: args.len(),
+            }
+            .into());
+        }
+
+        let body = body.as_ref().clone();
+        let call = Call {
+            name,
+            args: args.clone(),
+            span: call_site,
+            body: body.clone(),
+            expansion: None,
+        };
+        enter(call, stack);
+
+        let new_env = Rc::new(RefCell::new(Env::extend(env)));
+        if let Some((recur, procedure)) = recur {
+            new_env.borrow_mut().set(&recur, procedure);
+        }
+        for (param, arg) in params.iter().zip(args) {
+            new_env.borrow_mut().set(param, arg);
+        }
+        Ok(eval_body(body, new_env, stack))
+    }
+
+    /// Call a native procedure with evaluated args
+    pub fn apply_native(callee: Expr, args: Vec<Expr>) -> anyhow::Result<Expr> {
+        match callee {
+            Expr::Native { name, arity, func } => {
+                arity.check(&name, args.len())?;
+                func(args)
+            }
+            _ => Err(LispError::NotCallable(callee.to_string()).into()),
+        }
+    }
+
+    /// Push the Return of a call, replacing the Return of a caller whose body
+    /// ends in this call
+    fn enter(call: Call, stack: &mut Vec<Cont>) {
+        if let Some(Cont::Return(_)) = stack.last() {
+            stack.pop();
+        }
+        stack.push(Cont::Return(call));
+    }
+
+    /// Special form keyword a renamed head symbol stands for, else head itself
+    fn keyword(head: Expr, env: &Rc<RefCell<Env>>) -> Expr {
+        let name = match &head {
+            Expr::Atom(Token::Symbol(sym)) if Alias::is_alias(sym) => env.borrow().unalias(sym),
+            _ => None,
+        };
         match name {
-            Expr::Atom(Token::Symbol(ref sym)) => {
-                env.borrow_mut().set(sym, expr);
-                return Ok(NIL);
+            Some(name) if is_special(&name) => Expr::Atom(Token::Symbol(name)),
+            _ => head,
+        }
+    }
+
+    /// Macro bound to the head of a form, unless a special form shadows it
+    fn macro_use(head: &Expr, env: &Rc<RefCell<Env>>) -> Option<Macro> {
+        match head {
+            Expr::Atom(Token::Symbol(sym)) if !is_special(sym) => env.borrow().get_macro(sym),
+            _ => None,
+        }
+    }
+
+    /// Expand a use in env of transformer with the unevaluated argument forms
+    ///
+    /// Syntax rules rewrite the use right away, a procedure runs its body.
+    fn expand(
+        transformer: Macro,
+        args: Expr,
+        env: &Rc<RefCell<Env>>,
+        call_site: Option<Span>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        let body = match &transformer.transformer {
+            Transformer::Procedure { body, .. } => body.clone(),
+            Transformer::Rules(rules) => {
+                return rules
+                    .expand(&transformer.name, args, env, &transformer.env)
+                    .map(Mode::Return)
             }
-            _ => panic!("Expect Token::Symbol, found {:?}", name),
+        };
+
+        let body_env = transformer.bind(args.clone())?;
+        let call = Call {
+            name: transformer.name,
+            args: elements(args)?,
+            span: call_site,
+            body: body.clone(),
+            expansion: None,
+        };
+        enter(call, stack);
+        Ok(eval_body(body, body_env, stack))
+    }
+
+    /// `(defmacro name (params...) body...)` binds name to a Macro in env
+    ///
+    /// A dotted `(params... . rest)` binds rest to the list of remaining forms.
+    fn eval_defmacro(args: Expr, env: &Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
+        let body = cdr(cdr(args.clone()));
+        let mut args = elements(args)?.into_iter();
+        let (name, params) = match (args.next(), args.next()) {
+            (Some(Expr::Atom(Token::Symbol(name))), Some(params)) => (name, params),
+            _ => {
+                return Err(
+                    LispError::BadSyntax("defmacro without name and parameters".into()).into(),
+                )
+            }
+        };
+
+        let transformer = Macro::procedure(&name, params, body, env.clone())?;
+        env.borrow_mut().set_macro(name, transformer);
+        Ok(NIL)
+    }
+
+    /// `(define-syntax name (syntax-rules ...))` binds name to a Macro in env
+    fn eval_define_syntax(args: Expr, env: &Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
+        let (name, spec) = assignment("define-syntax", args)?;
+        let transformer = Macro::rules(&name, spec, env.clone())?;
+        env.borrow_mut().set_macro(name, transformer);
+        Ok(NIL)
+    }
+
+    /// `(let-syntax ((name (syntax-rules ...))...) body...)` binds the macros,
+    /// defined in env, in a new frame for body
+    fn eval_let_syntax(
+        args: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        let (bindings, body) = let_parts("let-syntax", args)?;
+        let new_env = Rc::new(RefCell::new(Env::extend(env.clone())));
+        for (name, spec) in self::bindings("let-syntax", bindings)? {
+            let transformer = Macro::rules(&name, spec, env.clone())?;
+            new_env.borrow_mut().set_macro(name, transformer);
         }
+        Ok(eval_body(body, new_env, stack))
     }
 
-    pub fn eval_cond(expr: Expr, env: &mut Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
-        Ok(collect(expr)
+    /// `(lambda (params...) body...)` evaluates to a Closure over env
+    pub fn eval_lambda(args: Expr, env: &Rc<RefCell<Env>>) -> anyhow::Result<Expr> {
+        if elements(args.clone())?.is_empty() {
+            return Err(LispError::BadSyntax("lambda without parameter list".into()).into());
+        }
+        let (params, body) = (car(args.clone()), cdr(args));
+
+        let params = elements(params)?
             .into_iter()
-            .find_map(|expr| match eval_expr(car(expr.clone()), env).ok()? {
-                Expr::Atom(Token::True) => eval_expr(cdr(expr), env).ok(),
-                _ => None,
+            .map(|param| match param {
+                Expr::Atom(Token::Symbol(sym)) => Ok(sym),
+                _ => Err(LispError::TypeMismatch {
+                    expected: "Token::Symbol",
+                    found: param.to_string(),
+                }
+                .into()),
             })
-            .unwrap_or(NIL))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Expr::Closure {
+            params,
+            body: ExprField::new(body),
+            env: env.clone(),
+            recur: None,
+        })
+    }
+
+    /// Name and value form of `(define name expr)` or `(set! name expr)`
+    fn assignment(form: &str, args: Expr) -> anyhow::Result<(String, Expr)> {
+        match arguments(form, args)? {
+            [Expr::Atom(Token::Symbol(sym)), expr] => Ok((sym, expr)),
+            [name, _] => Err(LispError::TypeMismatch {
+                expected: "Token::Symbol",
+                found: name.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    fn eval_define(
+        args: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        let (name, expr) = assignment("define", args)?;
+        stack.push(Cont::Define {
+            name,
+            env: env.clone(),
+        });
+        Ok(Mode::Eval(expr, env))
+    }
+
+    /// `(set! name expr)` rebinds name in the nearest Env defining it
+    fn eval_set(
+        form: Expr,
+        args: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        let (name, expr) = assignment("set!", args)?;
+        stack.push(Cont::Set {
+            form,
+            name,
+            env: env.clone(),
+        });
+        Ok(Mode::Eval(expr, env))
+    }
+
+    fn eval_cond(
+        form: Expr,
+        clauses: Expr,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        let mut clauses = elements(clauses)?;
+        clauses.reverse();
+        next_clause(form, clauses, env, stack)
+    }
+
+    /// Evaluate the test of the next of the reversed clauses, NIL once none is left
+    fn next_clause(
+        form: Expr,
+        mut clauses: Vec<Expr>,
+        env: Rc<RefCell<Env>>,
+        stack: &mut Vec<Cont>,
+    ) -> anyhow::Result<Mode> {
+        let clause = match clauses.pop() {
+            Some(clause) => clause,
+            None => return Ok(Mode::Return(NIL)),
+        };
+        if elements(clause.clone())?.is_empty() {
+            return Err(LispError::BadSyntax("empty cond clause".into()).into());
+        }
+        let test = car(clause.clone());
+
+        stack.push(Cont::Cond {
+            form,
+            body: cdr(clause),
+            clauses,
+            env: env.clone(),
+        });
+        Ok(Mode::Eval(test, env))
     }
 }
@@ -1,11 +1,149 @@
+use crate::env::Env;
+use crate::error::LispError;
+use crate::eval::{Continuation, Control};
 use crate::lexer::Token;
+use std::cell::RefCell;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// Shared car or cdr of a Composed Expr
+///
+/// Dropping the last reference to a long or deeply nested list frees its
+/// cells one at a time rather than recursively.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ExprField(Rc<Expr>);
+
+impl ExprField {
+    pub fn new(expr: Expr) -> Self {
+        Self(Rc::new(expr))
+    }
+
+    /// The Expr, cloned only if it is still shared
+    pub fn into_inner(mut self) -> Expr {
+        match Rc::get_mut(&mut self.0) {
+            Some(expr) => std::mem::replace(expr, consts::NIL),
+            None => self.0.as_ref().clone(),
+        }
+    }
+
+    /// Address identifying the shared Expr
+    pub fn as_ptr(&self) -> *const Expr {
+        Rc::as_ptr(&self.0)
+    }
+}
+
+impl std::ops::Deref for ExprField {
+    type Target = Expr;
+
+    fn deref(&self) -> &Expr {
+        &self.0
+    }
+}
 
-type ExprField = Box<Expr>;
+impl AsRef<Expr> for ExprField {
+    fn as_ref(&self) -> &Expr {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ExprField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for ExprField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Drop for ExprField {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        // Take the list a field alone refers to, leaving NIL behind
+        let detach = |field: &mut ExprField, pending: &mut Vec<Expr>| {
+            if let Some(expr @ Expr::Composed { .. }) = Rc::get_mut(&mut field.0) {
+                pending.push(std::mem::replace(expr, consts::NIL));
+            }
+        };
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+        detach(self, &mut pending);
+        while let Some(expr) = pending.pop() {
+            if let Expr::Composed { mut car, mut cdr } = expr {
+                detach(&mut car, &mut pending);
+                detach(&mut cdr, &mut pending);
+            }
+        }
+    }
+}
+
+/// Rust function behind a native procedure, called with evaluated arguments
+pub type NativeFn = Rc<dyn Fn(Vec<Expr>) -> anyhow::Result<Expr>>;
+
+/// Number of arguments a native procedure accepts
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Arity {
+    Exact(usize),
+    /// Variadic with a required minimum
+    AtLeast(usize),
+}
+
+impl Arity {
+    /// Check count against the arity, naming the procedure on mismatch
+    pub fn check(self, name: &str, count: usize) -> anyhow::Result<()> {
+        match self {
+            Self::Exact(expected) if count != expected => Err(LispError::ArityMismatch {
+                name: name.into(),
+                expected,
+                found: count,
+            }
+            .into()),
+            Self::AtLeast(expected) if count < expected => Err(LispError::TooFewArguments {
+                name: name.into(),
+                expected,
+                found: count,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<usize> for Arity {
+    fn from(count: usize) -> Self {
+        Self::Exact(count)
+    }
+}
+
+#[derive(Clone)]
 pub enum Expr {
     Atom(Token),
-    Composed { car: ExprField, cdr: ExprField },
+    Composed {
+        car: ExprField,
+        cdr: ExprField,
+    },
+    /// Lambda together with the Env it was evaluated in
+    Closure {
+        params: Vec<String>,
+        /// Body list as written in the lambda, keeping its source position
+        body: ExprField,
+        env: Rc<RefCell<Env>>,
+        /// Name a named let procedure is bound to in each of its calls, so
+        /// env need not refer back to it
+        recur: Option<String>,
+    },
+    /// Procedure implemented in Rust
+    Native {
+        name: String,
+        arity: Arity,
+        func: NativeFn,
+    },
+    /// Rest of an evaluation captured by `call/cc`
+    Continuation(Continuation),
+    /// Procedure such as `call/cc` that the evaluator applies itself
+    Control(Control),
 }
 
 impl Expr {
@@ -15,12 +153,187 @@ impl Expr {
 
     pub fn new_composed(car: Expr, cdr: Expr) -> Self {
         Self::Composed {
-            car: Box::new(car),
-            cdr: Box::new(cdr),
+            car: ExprField::new(car),
+            cdr: ExprField::new(cdr),
+        }
+    }
+}
+
+/// Closures are equal only if they share their captured Env
+///
+/// Lists are compared cell by cell without recursing, so long and deeply
+/// nested lists do not overflow the stack.
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        let mut pending = vec![(self, other)];
+        while let Some(pair) = pending.pop() {
+            let equal = match pair {
+                (Self::Atom(lhs), Self::Atom(rhs)) => lhs == rhs,
+                (
+                    Self::Composed { car, cdr },
+                    Self::Composed {
+                        car: other_car,
+                        cdr: other_cdr,
+                    },
+                ) => {
+                    pending.push((cdr, other_cdr));
+                    pending.push((car, other_car));
+                    true
+                }
+                (
+                    Self::Closure {
+                        params,
+                        body,
+                        env,
+                        recur,
+                    },
+                    Self::Closure {
+                        params: other_params,
+                        body: other_body,
+                        env: other_env,
+                        recur: other_recur,
+                    },
+                ) => {
+                    pending.push((body, other_body));
+                    params == other_params && Rc::ptr_eq(env, other_env) && recur == other_recur
+                }
+                (
+                    Self::Native { name, arity, func },
+                    Self::Native {
+                        name: other_name,
+                        arity: other_arity,
+                        func: other_func,
+                    },
+                ) => name == other_name && arity == other_arity && Rc::ptr_eq(func, other_func),
+                (Self::Continuation(lhs), Self::Continuation(rhs)) => lhs == rhs,
+                (Self::Control(lhs), Self::Control(rhs)) => lhs == rhs,
+                _ => false,
+            };
+            if !equal {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Eq for Expr {}
+
+impl Hash for Expr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            match expr {
+                Self::Atom(token) => token.hash(state),
+                Self::Composed { car, cdr } => {
+                    pending.push(cdr);
+                    pending.push(car);
+                }
+                Self::Closure {
+                    params,
+                    body,
+                    env,
+                    recur,
+                } => {
+                    params.hash(state);
+                    recur.hash(state);
+                    Rc::as_ptr(env).hash(state);
+                    pending.push(body);
+                }
+                Self::Native { name, arity, func } => {
+                    name.hash(state);
+                    arity.hash(state);
+                    Rc::as_ptr(func).cast::<()>().hash(state);
+                }
+                Self::Continuation(continuation) => continuation.hash(state),
+                Self::Control(control) => control.hash(state),
+            }
+        }
+    }
+}
+
+/// Skips the captured Env of closures, which may refer back to the closure
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Atom(token) => f.debug_tuple("Atom").field(token).finish(),
+            Self::Composed { car, cdr } => f
+                .debug_struct("Composed")
+                .field("car", car)
+                .field("cdr", cdr)
+                .finish(),
+            Self::Closure { params, body, .. } => f
+                .debug_struct("Closure")
+                .field("params", params)
+                .field("body", body)
+                .finish_non_exhaustive(),
+            Self::Native { name, arity, .. } => f
+                .debug_struct("Native")
+                .field("name", name)
+                .field("arity", arity)
+                .finish_non_exhaustive(),
+            Self::Continuation(continuation) => continuation.fmt(f),
+            Self::Control(control) => control.fmt(f),
         }
     }
 }
 
+/// Prints as an S-Expression that reads back to an equal Expr
+///
+/// Proper lists print as `(a b c)`, other pairs fall back to dotted `(a b . c)`.
+/// Closures print as an unreadable `#<lambda (params)>`, native procedures as
+/// `#<procedure name>` and continuations as `#<continuation>`.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /// Part of the printed Expr left to write
+        enum Piece<'a> {
+            Expr(&'a Expr),
+            Text(&'static str),
+        }
+
+        // Lists are written without recursion, next piece last
+        let mut pending = vec![Piece::Expr(self)];
+        while let Some(next) = pending.pop() {
+            let expr = match next {
+                Piece::Expr(expr) => expr,
+                Piece::Text(text) => {
+                    f.write_str(text)?;
+                    continue;
+                }
+            };
+            match expr {
+                Self::Atom(token) => write!(f, "{}", token)?,
+                Self::Closure { params, .. } => write!(f, "#<lambda ({})>", params.join(" "))?,
+                Self::Native { name, .. } => write!(f, "#<procedure {}>", name)?,
+                Self::Continuation(_) => write!(f, "#<continuation>")?,
+                Self::Control(control) => write!(f, "#<procedure {}>", control.name())?,
+                Self::Composed { .. } => {
+                    let mut elements = Vec::new();
+                    let mut rest = expr;
+                    while let Self::Composed { car, cdr } = rest {
+                        elements.push(car.as_ref());
+                        rest = cdr;
+                    }
+
+                    f.write_str("(")?;
+                    pending.push(Piece::Text(")"));
+                    if !matches!(rest, Self::Atom(Token::Nil)) {
+                        pending.push(Piece::Expr(rest));
+                        pending.push(Piece::Text(" . "));
+                    }
+                    for (i, element) in elements.into_iter().enumerate().rev() {
+                        pending.push(Piece::Expr(element));
+                        if i > 0 {
+                            pending.push(Piece::Text(" "));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 pub mod consts {
     use super::{Expr, Token};
 
@@ -37,6 +350,8 @@ pub mod consts {
     pub const APPLY: Expr = Expr::new_atom(Token::Apply);
     /// = Expr::Atom(Token::Define)
     pub const DEFINE: Expr = Expr::new_atom(Token::Define);
+    /// = Expr::Atom(Token::Set)
+    pub const SET: Expr = Expr::new_atom(Token::Set);
     /// = Expr::Atom(Token::Cond)
     pub const COND: Expr = Expr::new_atom(Token::Cond);
 }
@@ -58,22 +373,22 @@ pub mod builtins {
 
     pub fn car(expr: Expr) -> Expr {
         match expr {
-            Expr::Composed { car, .. } => *car,
+            Expr::Composed { car, .. } => car.into_inner(),
             _ => NIL,
         }
     }
 
     pub fn cdr(expr: Expr) -> Expr {
         match expr {
-            Expr::Composed { cdr, .. } => *cdr,
+            Expr::Composed { cdr, .. } => cdr.into_inner(),
             _ => NIL,
         }
     }
 
     pub fn atom(expr: Expr) -> Expr {
         match expr {
-            Expr::Atom(_) => TRUE,
-            _ => FALSE,
+            Expr::Composed { .. } => FALSE,
+            _ => TRUE,
         }
     }
 
@@ -104,145 +419,466 @@ pub mod builtins {
 pub mod math {
     use super::consts::*;
     use super::{Expr, Token};
+    use crate::error::LispError;
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+    use num_traits::{One, ToPrimitive, Zero};
+    use std::cmp::Ordering;
 
-    pub fn add(lhs: Expr, rhs: Expr) -> Expr {
-        match (lhs, rhs) {
-            (Expr::Atom(Token::Integer(lhs)), Expr::Atom(Token::Integer(rhs))) => {
-                Expr::Atom(Token::Integer(lhs + rhs))
+    /// Numeric operand unpacked from an Expr
+    ///
+    /// Integers and rationals are exact, floats inexact. Combining the two
+    /// yields a float.
+    #[derive(Clone)]
+    enum Number {
+        Integer(i64),
+        BigInt(BigInt),
+        Rational(BigRational),
+        Float(f64),
+    }
+
+    impl Number {
+        fn from_expr(expr: Expr) -> anyhow::Result<Self> {
+            match expr {
+                Expr::Atom(Token::Integer(n)) => Ok(Self::Integer(n)),
+                Expr::Atom(Token::BigInt(n)) => Ok(Self::BigInt(n)),
+                Expr::Atom(Token::Rational(r)) => Ok(Self::Rational(r)),
+                Expr::Atom(Token::Float(x)) => Ok(Self::Float(x)),
+                found => Err(LispError::TypeMismatch {
+                    expected: "number",
+                    found: found.to_string(),
+                }
+                .into()),
             }
-            _ => NIL,
         }
-    }
 
-    pub fn sub(lhs: Expr, rhs: Expr) -> Expr {
-        match (lhs, rhs) {
-            (Expr::Atom(Token::Integer(lhs)), Expr::Atom(Token::Integer(rhs))) => {
-                Expr::Atom(Token::Integer(lhs - rhs))
+        /// Demotes a BigInt that fits in i64
+        fn from_big(n: BigInt) -> Self {
+            match n.to_i64() {
+                Some(n) => Self::Integer(n),
+                None => Self::BigInt(n),
+            }
+        }
+
+        /// Demotes a rational with denominator 1 to an integer
+        fn from_ratio(r: BigRational) -> Self {
+            match r.is_integer() {
+                true => Self::from_big(r.to_integer()),
+                false => Self::Rational(r),
+            }
+        }
+
+        fn into_expr(self) -> Expr {
+            match self {
+                Self::Integer(n) => Expr::Atom(Token::Integer(n)),
+                Self::BigInt(n) => Expr::Atom(Token::BigInt(n)),
+                Self::Rational(r) => Expr::Atom(Token::Rational(r)),
+                Self::Float(x) => Expr::Atom(Token::Float(x)),
+            }
+        }
+
+        /// Exact value, None for floats
+        fn to_ratio(&self) -> Option<BigRational> {
+            match self {
+                Self::Integer(n) => Some(BigRational::from_integer(BigInt::from(*n))),
+                Self::BigInt(n) => Some(BigRational::from_integer(n.clone())),
+                Self::Rational(r) => Some(r.clone()),
+                Self::Float(_) => None,
+            }
+        }
+
+        fn to_f64(&self) -> f64 {
+            match self {
+                Self::Integer(n) => *n as f64,
+                Self::BigInt(n) => n.to_f64().unwrap_or(f64::NAN),
+                Self::Rational(r) => r.to_f64().unwrap_or(f64::NAN),
+                Self::Float(x) => *x,
+            }
+        }
+
+        fn is_exact_zero(&self) -> bool {
+            match self {
+                Self::Integer(n) => *n == 0,
+                Self::BigInt(n) => n.is_zero(),
+                Self::Rational(r) => r.is_zero(),
+                Self::Float(_) => false,
+            }
+        }
+
+        /// None if either side is NaN
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            match (self, other) {
+                (Self::Integer(lhs), Self::Integer(rhs)) => Some(lhs.cmp(rhs)),
+                (lhs, rhs) => match (lhs.to_ratio(), rhs.to_ratio()) {
+                    (Some(lhs), Some(rhs)) => Some(lhs.cmp(&rhs)),
+                    _ => lhs.to_f64().partial_cmp(&rhs.to_f64()),
+                },
+            }
+        }
+
+        /// Combine with checked i64 arithmetic, falling back to exact rationals
+        /// on overflow and to float if either side is a float
+        fn arith(
+            self,
+            other: Self,
+            checked: fn(i64, i64) -> Option<i64>,
+            exact: fn(BigRational, BigRational) -> BigRational,
+            float: fn(f64, f64) -> f64,
+        ) -> Self {
+            if let (Self::Integer(lhs), Self::Integer(rhs)) = (&self, &other) {
+                if let Some(n) = checked(*lhs, *rhs) {
+                    return Self::Integer(n);
+                }
+            }
+            match (self.to_ratio(), other.to_ratio()) {
+                (Some(lhs), Some(rhs)) => Self::from_ratio(exact(lhs, rhs)),
+                _ => Self::Float(float(self.to_f64(), other.to_f64())),
+            }
+        }
+
+        fn add(self, other: Self) -> Self {
+            self.arith(
+                other,
+                i64::checked_add,
+                |lhs, rhs| lhs + rhs,
+                |lhs, rhs| lhs + rhs,
+            )
+        }
+
+        fn sub(self, other: Self) -> Self {
+            self.arith(
+                other,
+                i64::checked_sub,
+                |lhs, rhs| lhs - rhs,
+                |lhs, rhs| lhs - rhs,
+            )
+        }
+
+        fn mul(self, other: Self) -> Self {
+            self.arith(
+                other,
+                i64::checked_mul,
+                |lhs, rhs| lhs * rhs,
+                |lhs, rhs| lhs * rhs,
+            )
+        }
+
+        /// Exact division yields a rational unless it divides evenly
+        ///
+        /// Dividing by an exact zero is an error, by a float zero gives infinity
+        fn div(self, other: Self) -> anyhow::Result<Self> {
+            match other.is_exact_zero() {
+                true => Err(LispError::DivisionByZero.into()),
+                false => Ok(self.arith(
+                    other,
+                    |lhs, rhs| match lhs.checked_rem(rhs) {
+                        Some(0) => lhs.checked_div(rhs),
+                        _ => None,
+                    },
+                    |lhs, rhs| lhs / rhs,
+                    |lhs, rhs| lhs / rhs,
+                )),
             }
-            _ => NIL,
         }
     }
 
-    pub fn mul(lhs: Expr, rhs: Expr) -> Expr {
-        match (lhs, rhs) {
-            (Expr::Atom(Token::Integer(lhs)), Expr::Atom(Token::Integer(rhs))) => {
-                Expr::Atom(Token::Integer(lhs * rhs))
+    fn numbers(args: Vec<Expr>) -> anyhow::Result<Vec<Number>> {
+        args.into_iter().map(Number::from_expr).collect()
+    }
+
+    /// The single Number of args for name
+    fn number(name: &str, args: Vec<Expr>) -> anyhow::Result<Number> {
+        let found = args.len();
+        match <[Expr; 1]>::try_from(args) {
+            Ok([arg]) => Number::from_expr(arg),
+            Err(_) => Err(LispError::ArityMismatch {
+                name: name.into(),
+                expected: 1,
+                found,
             }
-            _ => NIL,
+            .into()),
         }
     }
 
-    pub fn div(lhs: Expr, rhs: Expr) -> Expr {
-        match (lhs, rhs) {
-            (Expr::Atom(Token::Integer(lhs)), Expr::Atom(Token::Integer(rhs))) => {
-                Expr::Atom(Token::Integer(lhs / rhs))
+    /// First of ns and the rest, requiring at least one for name
+    fn split_first(name: &str, ns: Vec<Number>) -> anyhow::Result<(Number, Vec<Number>)> {
+        let mut ns = ns.into_iter();
+        match ns.next() {
+            Some(first) => Ok((first, ns.collect())),
+            None => Err(LispError::TooFewArguments {
+                name: name.into(),
+                expected: 1,
+                found: 0,
             }
-            _ => NIL,
+            .into()),
         }
     }
-}
 
-pub mod intrinsics {
-    use std::collections::HashSet;
-    use std::sync::LazyLock;
+    /// Sum of args, 0 if there are none
+    pub fn add(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        let sum = numbers(args)?
+            .into_iter()
+            .fold(Number::Integer(0), Number::add);
+        Ok(sum.into_expr())
+    }
 
-    use super::builtins::*;
-    use super::{Expr, Token};
+    /// First arg minus the rest, or its negation if it is alone
+    pub fn sub(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        let (first, rest) = split_first("-", numbers(args)?)?;
+        let difference = match rest.is_empty() {
+            true => Number::Integer(0).sub(first),
+            false => rest.into_iter().fold(first, Number::sub),
+        };
+        Ok(difference.into_expr())
+    }
+
+    /// Product of args, 1 if there are none
+    pub fn mul(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        let product = numbers(args)?
+            .into_iter()
+            .fold(Number::Integer(1), Number::mul);
+        Ok(product.into_expr())
+    }
 
-    /// Append to a S-Expression if it's not empty
-    pub fn append(lhs: Expr, rhs: Expr) -> Expr {
-        if let Expr::Atom(Token::Nil) = lhs {
-            rhs
-        } else {
-            cons(lhs, rhs)
+    /// First arg divided by the rest, or its reciprocal if it is alone
+    pub fn div(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        let (first, rest) = split_first("/", numbers(args)?)?;
+        let (mut quotient, rest) = match rest.is_empty() {
+            true => (Number::Integer(1), vec![first]),
+            false => (first, rest),
+        };
+        for n in rest {
+            quotient = quotient.div(n)?;
         }
+        Ok(quotient.into_expr())
     }
 
-    /// Get first atom in a S-Expression
-    pub fn left_most(expr: Expr) -> Expr {
-        match expr {
-            Expr::Atom(_) => expr,
-            _ => left_most(car(expr)),
+    /// TRUE if every adjacent pair of args satisfies ordered
+    fn chain(args: Vec<Expr>, ordered: fn(Ordering) -> bool) -> anyhow::Result<Expr> {
+        let ns = numbers(args)?;
+        let chained = ns
+            .windows(2)
+            .all(|pair| pair[0].partial_cmp(&pair[1]).is_some_and(ordered));
+        match chained {
+            true => Ok(TRUE),
+            false => Ok(FALSE),
         }
     }
 
-    /// Flatten a S-Expression to atoms vector
-    pub fn flatten(expr: Expr) -> Vec<Expr> {
-        let mut exprs = Vec::new();
+    pub fn less(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        chain(args, Ordering::is_lt)
+    }
 
-        match expr {
-            Expr::Atom(_) => exprs.push(expr),
-            _ => {
-                exprs.extend(flatten(car(expr.clone())));
-                exprs.extend(flatten(cdr(expr)));
-            }
+    pub fn greater(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        chain(args, Ordering::is_gt)
+    }
+
+    pub fn less_equal(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        chain(args, Ordering::is_le)
+    }
+
+    pub fn greater_equal(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        chain(args, Ordering::is_ge)
+    }
+
+    pub fn equal(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        chain(args, Ordering::is_eq)
+    }
+
+    pub fn exact_to_inexact(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        let x = number("exact->inexact", args)?.to_f64();
+        Ok(Number::Float(x).into_expr())
+    }
+
+    /// Finite floats convert to the integer or rational they represent
+    pub fn inexact_to_exact(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        match number("inexact->exact", args)? {
+            Number::Float(x) => match BigRational::from_float(x) {
+                Some(r) => Ok(Number::from_ratio(r).into_expr()),
+                None => Err(LispError::TypeMismatch {
+                    expected: "finite number",
+                    found: Number::Float(x).into_expr().to_string(),
+                }
+                .into()),
+            },
+            n => Ok(n.into_expr()),
         }
+    }
 
-        exprs
+    /// Round with exact or float, leaving integers unchanged
+    fn integral(
+        name: &str,
+        args: Vec<Expr>,
+        exact: fn(&BigRational) -> BigRational,
+        float: fn(f64) -> f64,
+    ) -> anyhow::Result<Expr> {
+        match number(name, args)? {
+            Number::Rational(r) => Ok(Number::from_ratio(exact(&r)).into_expr()),
+            Number::Float(x) => Ok(Number::Float(float(x)).into_expr()),
+            n => Ok(n.into_expr()),
+        }
     }
 
-    /// Gather parameters of Token::Cond | Token::Apply
-    pub fn collect(expr: Expr) -> Vec<Expr> {
-        let mut bottom = false;
-        collect_(expr, &mut bottom)
+    pub fn floor(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        integral("floor", args, BigRational::floor, f64::floor)
     }
 
-    fn collect_(expr: Expr, bottom: &mut bool) -> Vec<Expr> {
-        let mut exprs = Vec::new();
+    pub fn ceiling(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        integral("ceiling", args, BigRational::ceil, f64::ceil)
+    }
 
-        match expr {
-            Expr::Atom(Token::Cond) | Expr::Atom(Token::Apply) => {
-                *bottom = true;
+    /// Rounds halfway cases to even
+    pub fn round(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        integral("round", args, round_ties_even, f64::round_ties_even)
+    }
+
+    fn round_ties_even(r: &BigRational) -> BigRational {
+        let floor = r.floor();
+        let half = BigRational::new(BigInt::one(), BigInt::from(2));
+        match (r - &floor).cmp(&half) {
+            Ordering::Less => floor,
+            Ordering::Greater => floor + BigInt::one(),
+            Ordering::Equal if (floor.to_integer() % 2u32).is_zero() => floor,
+            Ordering::Equal => floor + BigInt::one(),
+        }
+    }
+
+    pub fn truncate(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        integral("truncate", args, BigRational::trunc, f64::trunc)
+    }
+
+    /// Exact if both parts of an exact argument are perfect squares
+    pub fn sqrt(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        let n = number("sqrt", args)?;
+        if n.partial_cmp(&Number::Integer(0)) == Some(Ordering::Less) {
+            return Err(LispError::TypeMismatch {
+                expected: "non-negative number",
+                found: n.into_expr().to_string(),
+            }
+            .into());
+        }
+        if let Some(exact) = n.to_ratio() {
+            let (numer, denom) = (exact.numer().sqrt(), exact.denom().sqrt());
+            let root = BigRational::new(numer, denom);
+            if &root * &root == exact {
+                return Ok(Number::from_ratio(root).into_expr());
             }
-            _ if *bottom => exprs.push(expr),
-            _ => {
-                exprs.extend(collect_(car(expr.clone()), bottom));
-                exprs.extend(collect_(cdr(expr), bottom));
+        }
+        Ok(Number::Float(n.to_f64().sqrt()).into_expr())
+    }
+
+    /// Numerator of a number in lowest terms, inexact for floats
+    pub fn numerator(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        fraction("numerator", args, |r| r.numer().clone())
+    }
+
+    /// Positive denominator of a number in lowest terms, inexact for floats
+    pub fn denominator(args: Vec<Expr>) -> anyhow::Result<Expr> {
+        fraction("denominator", args, |r| r.denom().clone())
+    }
+
+    fn fraction(
+        name: &str,
+        args: Vec<Expr>,
+        part: fn(&BigRational) -> BigInt,
+    ) -> anyhow::Result<Expr> {
+        let n = number(name, args)?;
+        let exact = match &n {
+            Number::Float(x) => BigRational::from_float(*x),
+            n => n.to_ratio(),
+        };
+        match (exact, n) {
+            (Some(r), Number::Float(_)) => {
+                Ok(Number::Float(Number::from_big(part(&r)).to_f64()).into_expr())
+            }
+            (Some(r), _) => Ok(Number::from_big(part(&r)).into_expr()),
+            (None, n) => Err(LispError::TypeMismatch {
+                expected: "finite number",
+                found: n.into_expr().to_string(),
             }
+            .into()),
         }
+    }
+}
 
+pub mod intrinsics {
+    use super::builtins::*;
+    use super::consts::*;
+    use super::math::*;
+    use super::{Arity, Expr, Token};
+    use crate::error::LispError;
+
+    /// Build a proper list ending in NIL
+    pub fn list(exprs: Vec<Expr>) -> Expr {
+        dotted_list(exprs, NIL)
+    }
+
+    /// Build a list ending in tail instead of NIL
+    pub fn dotted_list(exprs: Vec<Expr>, tail: Expr) -> Expr {
         exprs
+            .into_iter()
+            .rev()
+            .fold(tail, |list, expr| cons(expr, list))
     }
 
-    /// Check if symbol is a unary operator
-    pub fn is_unary(expr: &Expr) -> bool {
-        match expr {
-            Expr::Atom(ref sym) if UNARIES.contains(sym) => true,
-            _ => false,
+    /// Elements of a proper list
+    pub fn elements(expr: Expr) -> anyhow::Result<Vec<Expr>> {
+        let mut exprs = Vec::new();
+        let mut rest = expr.clone();
+
+        loop {
+            match rest {
+                Expr::Atom(Token::Nil) => return Ok(exprs),
+                Expr::Composed { car, cdr } => {
+                    exprs.push(car.into_inner());
+                    rest = cdr.into_inner();
+                }
+                _ => {
+                    return Err(LispError::TypeMismatch {
+                        expected: "proper list",
+                        found: expr.to_string(),
+                    }
+                    .into())
+                }
+            }
         }
     }
 
-    /// Check if symbol is a binary operator
-    pub fn is_binary(expr: &Expr) -> bool {
-        match expr {
-            Expr::Atom(ref sym) if BINARIES.contains(sym) => true,
-            _ => false,
-        }
-    }
-
-    static UNARIES: LazyLock<HashSet<Token>> = LazyLock::new(|| {
-        let mut set = HashSet::new();
-        set.insert(Token::Symbol("car".into()));
-        set.insert(Token::Symbol("cdr".into()));
-        set.insert(Token::Symbol("atom".into()));
-        set.insert(Token::Symbol("null".into()));
-        set.insert(Token::Symbol("quote".into()));
-        set.insert(Token::Symbol("eval".into()));
-        set
-    });
-
-    static BINARIES: LazyLock<HashSet<Token>> = LazyLock::new(|| {
-        let mut set = HashSet::new();
-        set.insert(Token::Symbol("cons".into()));
-        set.insert(Token::Symbol("eq".into()));
-        set.insert(Token::Symbol("add".into()));
-        set.insert(Token::Symbol("sub".into()));
-        set.insert(Token::Symbol("mul".into()));
-        set.insert(Token::Symbol("div".into()));
-        set.insert(Token::Symbol("+".into()));
-        set.insert(Token::Symbol("-".into()));
-        set.insert(Token::Symbol("*".into()));
-        set.insert(Token::Symbol("/".into()));
-        set
-    });
+    type Builtin = fn(Vec<Expr>) -> anyhow::Result<Expr>;
+
+    /// Builtin procedures bound in every new global Env, as (name, arity, func)
+    pub static NATIVES: &[(&str, Arity, Builtin)] = &[
+        ("car", Arity::Exact(1), |args| Ok(car(args[0].clone()))),
+        ("cdr", Arity::Exact(1), |args| Ok(cdr(args[0].clone()))),
+        ("atom", Arity::Exact(1), |args| Ok(atom(args[0].clone()))),
+        ("null", Arity::Exact(1), |args| Ok(null(args[0].clone()))),
+        ("cons", Arity::Exact(2), |args| {
+            Ok(cons(args[0].clone(), args[1].clone()))
+        }),
+        ("eq", Arity::Exact(2), |args| {
+            Ok(eq(args[0].clone(), args[1].clone()))
+        }),
+        ("add", Arity::AtLeast(0), add),
+        ("sub", Arity::AtLeast(1), sub),
+        ("mul", Arity::AtLeast(0), mul),
+        ("div", Arity::AtLeast(1), div),
+        ("+", Arity::AtLeast(0), add),
+        ("-", Arity::AtLeast(1), sub),
+        ("*", Arity::AtLeast(0), mul),
+        ("/", Arity::AtLeast(1), div),
+        ("<", Arity::AtLeast(1), less),
+        (">", Arity::AtLeast(1), greater),
+        ("<=", Arity::AtLeast(1), less_equal),
+        (">=", Arity::AtLeast(1), greater_equal),
+        ("=", Arity::AtLeast(1), equal),
+        ("exact->inexact", Arity::Exact(1), exact_to_inexact),
+        ("inexact->exact", Arity::Exact(1), inexact_to_exact),
+        ("floor", Arity::Exact(1), floor),
+        ("ceiling", Arity::Exact(1), ceiling),
+        ("round", Arity::Exact(1), round),
+        ("truncate", Arity::Exact(1), truncate),
+        ("sqrt", Arity::Exact(1), sqrt),
+        ("numerator", Arity::Exact(1), numerator),
+        ("denominator", Arity::Exact(1), denominator),
+    ];
 }
@@ -1,13 +1,30 @@
+use crate::error::LispError;
+use crate::macros::Alias;
+use crate::span::{Source, Span};
+use num_bigint::BigInt;
+use num_rational::BigRational;
 use std::collections::VecDeque;
 use std::convert::AsRef;
+use std::fmt;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::io::Read;
+use std::iter::Peekable;
+use std::mem;
 use std::path::Path;
+use std::str::CharIndices;
+use std::sync::Arc;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone)]
 pub enum Token {
-    Integer(i32),
+    Integer(i64),
+    /// Integer beyond the range of i64
+    BigInt(BigInt),
+    /// Exact fraction in lowest terms with denominator above 1
+    Rational(BigRational),
+    Float(f64),
     Symbol(String),
+    String(String),
     LParen,
     RParen,
     Nil,
@@ -16,45 +33,591 @@ pub enum Token {
     Lambda,
     Apply,
     Define,
+    Set,
     Cond,
+    Dot,
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+}
+
+/// Floats compare by bit pattern, so Token can be Eq and Hash
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Integer(lhs), Self::Integer(rhs)) => lhs == rhs,
+            (Self::BigInt(lhs), Self::BigInt(rhs)) => lhs == rhs,
+            (Self::Rational(lhs), Self::Rational(rhs)) => lhs == rhs,
+            (Self::Float(lhs), Self::Float(rhs)) => lhs.to_bits() == rhs.to_bits(),
+            (Self::Symbol(lhs), Self::Symbol(rhs)) | (Self::String(lhs), Self::String(rhs)) => {
+                lhs == rhs
+            }
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for Token {}
+
+impl Hash for Token {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Self::Integer(i) => i.hash(state),
+            Self::BigInt(i) => i.hash(state),
+            Self::Rational(r) => r.hash(state),
+            Self::Float(x) => x.to_bits().hash(state),
+            Self::Symbol(string) | Self::String(string) => string.hash(state),
+            _ => (),
+        }
+    }
+}
+
+/// Symbols renamed by a macro expansion print as the name they were written as
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::BigInt(i) => write!(f, "{}", i),
+            Self::Rational(r) => write!(f, "{}", r),
+            Self::Float(x) if x.is_nan() => write!(f, "+nan.0"),
+            Self::Float(x) if x.is_infinite() => {
+                write!(f, "{}inf.0", if *x > 0.0 { "+" } else { "-" })
+            }
+            Self::Float(x) => write!(f, "{:?}", x),
+            Self::Symbol(sym) => write!(f, "{}", Alias::base(sym)),
+            Self::String(string) => {
+                write!(f, "\"")?;
+                for c in string.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\0' => write!(f, "\\0")?,
+                        _ if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+                        _ => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Self::LParen => write!(f, "("),
+            Self::RParen => write!(f, ")"),
+            Self::Nil => write!(f, "nil"),
+            Self::True => write!(f, "t"),
+            Self::False => write!(f, "f"),
+            Self::Lambda => write!(f, "lambda"),
+            Self::Apply => write!(f, "apply"),
+            Self::Define => write!(f, "define"),
+            Self::Set => write!(f, "set!"),
+            Self::Cond => write!(f, "cond"),
+            Self::Dot => write!(f, "."),
+            Self::Quote => write!(f, "'"),
+            Self::Quasiquote => write!(f, "`"),
+            Self::Unquote => write!(f, ","),
+            Self::UnquoteSplicing => write!(f, ",@"),
+        }
+    }
 }
 
 pub struct Lexer;
 
 impl Lexer {
     pub fn tokenize_file(path: impl AsRef<Path>) -> anyhow::Result<VecDeque<Token>> {
+        Ok(Self::strip(Self::tokenize_spanned(Self::read_file(path)?)?))
+    }
+
+    pub fn tokenize(source: impl AsRef<str>) -> anyhow::Result<VecDeque<Token>> {
+        Ok(Self::strip(Self::tokenize_spanned(Source::new(
+            "<input>",
+            source.as_ref(),
+        ))?))
+    }
+
+    /// Read a file into a Source named by its path
+    pub fn read_file(path: impl AsRef<Path>) -> anyhow::Result<Arc<Source>> {
         let mut file = fs::OpenOptions::new().read(true).open(path.as_ref())?;
 
-        let mut source = String::new();
-        file.read_to_string(&mut source)?;
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
 
-        Self::tokenize(source)
+        Ok(Source::new(path.as_ref().display().to_string(), text))
     }
 
-    pub fn tokenize(source: impl AsRef<str>) -> anyhow::Result<VecDeque<Token>> {
-        Ok(source
-            .as_ref()
-            .replace("(", " ( ")
-            .replace(")", " ) ")
-            .split_whitespace()
-            .map(|x| match x.to_ascii_lowercase().as_str() {
-                "(" => Token::LParen,
-                ")" => Token::RParen,
-                "lambda" => Token::Lambda,
-                "apply" => Token::Apply,
-                "define" => Token::Define,
-                "cond" => Token::Cond,
-                "t" => Token::True,
-                "f" => Token::False,
-                "nil" => Token::Nil,
+    /// Tokenize a Source, keeping the Span of every Token
+    pub fn tokenize_spanned(source: Arc<Source>) -> anyhow::Result<VecDeque<(Token, Span)>> {
+        // None marks a `#;` datum comment, resolved once all tokens are known
+        let mut lexemes = VecDeque::new();
+        let mut cursor = Cursor::new(&source);
+
+        while let Some(c) = cursor.peek() {
+            let mark = cursor.mark();
+
+            let token = match c {
+                _ if c.is_whitespace() => {
+                    cursor.bump();
+                    continue;
+                }
+                ';' => {
+                    while cursor.peek().is_some_and(|c| c != '\n') {
+                        cursor.bump();
+                    }
+                    continue;
+                }
+                '#' if cursor.starts_with("#|") => {
+                    Self::block_comment(&mut cursor, &mark)?;
+                    continue;
+                }
+                '#' if cursor.starts_with("#;") => {
+                    cursor.bump();
+                    cursor.bump();
+                    None
+                }
+                '(' => {
+                    cursor.bump();
+                    Some(Token::LParen)
+                }
+                ')' => {
+                    cursor.bump();
+                    Some(Token::RParen)
+                }
+                '\'' => {
+                    cursor.bump();
+                    Some(Token::Quote)
+                }
+                '`' => {
+                    cursor.bump();
+                    Some(Token::Quasiquote)
+                }
+                ',' if cursor.starts_with(",@") => {
+                    cursor.bump();
+                    cursor.bump();
+                    Some(Token::UnquoteSplicing)
+                }
+                ',' => {
+                    cursor.bump();
+                    Some(Token::Unquote)
+                }
+                '"' => Some(Self::string(&mut cursor, &mark)?),
                 _ => {
-                    if let Ok(i) = x.parse::<i32>() {
-                        Token::Integer(i)
-                    } else {
-                        Token::Symbol(x.into())
+                    while cursor.peek().is_some_and(|c| !is_delimiter(c)) {
+                        cursor.bump();
+                    }
+                    let lexeme = &source.text[mark.range.start..cursor.offset()];
+                    match Self::classify(lexeme) {
+                        Ok(token) => Some(token),
+                        Err(err) => return Err(err.at(cursor.close(mark))),
+                    }
+                }
+            };
+
+            lexemes.push_back((token, cursor.close(mark)));
+        }
+
+        let mut tokens = VecDeque::new();
+        while let Some((token, span)) = lexemes.pop_front() {
+            match token {
+                Some(token) => tokens.push_back((token, span)),
+                None => Self::skip_datum(&mut lexemes, span)?,
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    /// Skip a possibly nested `#| ... |#` comment, cursor is at the opening `#|`
+    fn block_comment(cursor: &mut Cursor, mark: &Span) -> anyhow::Result<()> {
+        let mut depth = 0;
+
+        loop {
+            if cursor.starts_with("#|") {
+                depth += 1;
+                cursor.bump();
+            } else if cursor.starts_with("|#") {
+                depth -= 1;
+                cursor.bump();
+                if depth == 0 {
+                    cursor.bump();
+                    return Ok(());
+                }
+            }
+
+            if cursor.bump().is_none() {
+                return Err(LispError::UnterminatedComment.at(mark.clone()));
+            }
+        }
+    }
+
+    /// Drop the datum following the `#;` datum comment at span
+    fn skip_datum(lexemes: &mut VecDeque<(Option<Token>, Span)>, span: Span) -> anyhow::Result<()> {
+        let mut depth = 0;
+
+        while let Some((token, at)) = lexemes.pop_front() {
+            match token {
+                None => {
+                    Self::skip_datum(lexemes, span.clone())?;
+                    continue;
+                }
+                Some(Token::LParen) => depth += 1,
+                Some(Token::RParen) if depth > 0 => depth -= 1,
+                // More input cannot supply the datum before a closing paren
+                Some(Token::RParen) => return Err(LispError::UnexpectedToken(Token::RParen).at(at)),
+                // A quote shorthand prefixes the datum that follows it
+                Some(
+                    Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing,
+                ) if depth == 0 => continue,
+                Some(_) => (),
+            }
+
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+
+        Err(LispError::MissingDatum.at(span))
+    }
+
+    /// Read a string literal, cursor is at the opening quote
+    fn string(cursor: &mut Cursor, mark: &Span) -> anyhow::Result<Token> {
+        let mut string = String::new();
+        cursor.bump();
+
+        loop {
+            let escape = cursor.mark();
+            match cursor.bump() {
+                Some('"') => return Ok(Token::String(string)),
+                Some('\\') => match cursor.bump() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('0') => string.push('\0'),
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('u') => string.push(Self::unicode(cursor, &escape)?),
+                    Some(c) => {
+                        let err = LispError::InvalidEscape(format!("\\{}", c));
+                        return Err(err.at(cursor.close(escape)));
                     }
+                    None => return Err(LispError::UnterminatedString.at(mark.clone())),
+                },
+                Some(c) => string.push(c),
+                None => return Err(LispError::UnterminatedString.at(mark.clone())),
+            }
+        }
+    }
+
+    /// Read the `{...}` part of a `\u{...}` escape
+    fn unicode(cursor: &mut Cursor, escape: &Span) -> anyhow::Result<char> {
+        let start = cursor.offset();
+        if cursor.bump() == Some('{') {
+            while cursor.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                cursor.bump();
+            }
+            let digits = &cursor.source.text[start + 1..cursor.offset()];
+            if cursor.bump() == Some('}') {
+                if let Some(c) = u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                {
+                    return Ok(c);
                 }
-            })
-            .collect())
+            }
+        }
+
+        let span = cursor.close(escape.clone());
+        let err = LispError::InvalidEscape(cursor.source.text[span.range.clone()].into());
+        Err(err.at(span))
+    }
+
+    /// Token for a symbol or number lexeme, failing if it is a malformed number
+    fn classify(x: &str) -> Result<Token, LispError> {
+        Ok(match x.to_ascii_lowercase().as_str() {
+            "lambda" => Token::Lambda,
+            "apply" => Token::Apply,
+            "define" => Token::Define,
+            "set!" => Token::Set,
+            "cond" => Token::Cond,
+            "t" => Token::True,
+            "f" => Token::False,
+            "nil" => Token::Nil,
+            "." => Token::Dot,
+            _ if number::is_numeric(x) => number::parse(x)?,
+            _ => Token::Symbol(x.into()),
+        })
+    }
+
+    fn strip(tokens: VecDeque<(Token, Span)>) -> VecDeque<Token> {
+        tokens.into_iter().map(|(token, _)| token).collect()
+    }
+}
+
+/// Characters that end a symbol or number
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == ';'
+}
+
+/// Character stream over a Source, tracking the position of the next char
+struct Cursor<'a> {
+    source: &'a Arc<Source>,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a Arc<Source>) -> Self {
+        Self {
+            source,
+            chars: source.text.char_indices().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn starts_with(&mut self, prefix: &str) -> bool {
+        let offset = self.offset();
+        self.source.text[offset..].starts_with(prefix)
+    }
+
+    /// Byte offset of the next char
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.source.text.len(), |&(i, _)| i)
+    }
+
+    /// Empty Span at the next char
+    fn mark(&mut self) -> Span {
+        let offset = self.offset();
+        Span {
+            source: self.source.clone(),
+            line: self.line,
+            column: self.column,
+            range: offset..offset,
+        }
+    }
+
+    /// Extend a marked Span up to the next char
+    fn close(&mut self, mut span: Span) -> Span {
+        span.range.end = self.offset();
+        span
+    }
+}
+
+/// Numeric literals
+///
+/// A number is an optional `#e`/`#i` exactness and `#x`/`#b`/`#o`/`#d` radix
+/// prefix in either order, then an integer, a fraction like `1/3` or, in
+/// radix 10, a decimal like `-1.5e3`. Digits may be separated by `_`.
+/// Decimals are inexact unless prefixed by `#e`, and exact ones take an
+/// exponent of at most 4096 in magnitude.
+mod number {
+    use super::Token;
+    use crate::error::LispError;
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+    use num_traits::{ToPrimitive, Zero};
+
+    /// Lexemes starting like a number, which must then parse as one
+    pub fn is_numeric(x: &str) -> bool {
+        if special(x).is_some() {
+            return true;
+        }
+        let mut chars = x.chars();
+        let digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+        match chars.next() {
+            Some('#') => chars.next().is_some_and(|c| {
+                matches!(c.to_ascii_lowercase(), 'e' | 'i' | 'x' | 'b' | 'o' | 'd')
+            }),
+            Some('+' | '-') => match chars.next() {
+                Some('.') => digit(chars.next()),
+                c => digit(c),
+            },
+            Some('.') => digit(chars.next()),
+            c => digit(c),
+        }
+    }
+
+    pub fn parse(x: &str) -> Result<Token, LispError> {
+        let malformed = || LispError::MalformedNumber(x.into());
+        let mut exact = None;
+        let mut radix = None;
+        let mut body = x;
+        while let Some(rest) = body.strip_prefix('#') {
+            let mut chars = rest.chars();
+            match chars.next().ok_or_else(malformed)?.to_ascii_lowercase() {
+                'e' if exact.is_none() => exact = Some(true),
+                'i' if exact.is_none() => exact = Some(false),
+                'x' if radix.is_none() => radix = Some(16),
+                'b' if radix.is_none() => radix = Some(2),
+                'o' if radix.is_none() => radix = Some(8),
+                'd' if radix.is_none() => radix = Some(10),
+                _ => return Err(malformed()),
+            }
+            body = chars.as_str();
+        }
+        let radix = radix.unwrap_or(10);
+        let body = separated(body, radix).ok_or_else(malformed)?;
+
+        if let Some(x) = special(&body) {
+            return (exact != Some(true))
+                .then_some(Token::Float(x))
+                .ok_or_else(malformed);
+        }
+
+        let value = match body.split_once('/') {
+            Some((numer, denom)) if !denom.starts_with(['+', '-']) => {
+                let numer = integer(numer, radix).ok_or_else(malformed)?;
+                let denom = integer(denom, radix).ok_or_else(malformed)?;
+                match denom.is_zero() {
+                    true => return Err(malformed()),
+                    false => BigRational::new(numer, denom),
+                }
+            }
+            Some(_) => return Err(malformed()),
+            None => match integer(&body, radix) {
+                Some(n) => BigRational::from_integer(n),
+                None if radix == 10 && exact == Some(true) => match decimal(&body) {
+                    Decimal::Value(value) => value,
+                    Decimal::TooLarge => return Err(LispError::ExponentTooLarge(x.into())),
+                    Decimal::Malformed => return Err(malformed()),
+                },
+                None if radix == 10 => {
+                    decimal_parts(&body).ok_or_else(malformed)?;
+                    return body.parse().map(Token::Float).map_err(|_| malformed());
+                }
+                None => return Err(malformed()),
+            },
+        };
+
+        match exact {
+            Some(false) => value.to_f64().map(Token::Float).ok_or_else(malformed),
+            _ => Ok(exact_token(value)),
+        }
+    }
+
+    /// Infinities and NaN
+    fn special(x: &str) -> Option<f64> {
+        match x.to_ascii_lowercase().as_str() {
+            "+inf.0" => Some(f64::INFINITY),
+            "-inf.0" => Some(f64::NEG_INFINITY),
+            "+nan.0" => Some(f64::NAN),
+            _ => None,
+        }
+    }
+
+    /// Token for an exact value, in the narrowest representation
+    fn exact_token(r: BigRational) -> Token {
+        match (r.is_integer(), r.to_integer().to_i64()) {
+            (true, Some(i)) => Token::Integer(i),
+            (true, None) => Token::BigInt(r.to_integer()),
+            (false, _) => Token::Rational(r),
+        }
+    }
+
+    /// Remove `_` digit separators, each of which must sit between two digits
+    fn separated(body: &str, radix: u32) -> Option<String> {
+        let chars = body.chars().collect::<Vec<_>>();
+        let digit = |i: Option<usize>| {
+            i.and_then(|i| chars.get(i))
+                .is_some_and(|c| c.is_digit(radix))
+        };
+        for (i, c) in chars.iter().enumerate() {
+            if *c == '_' && !(digit(i.checked_sub(1)) && digit(Some(i + 1))) {
+                return None;
+            }
+        }
+        Some(chars.iter().filter(|c| **c != '_').collect())
+    }
+
+    /// Signed integer in radix
+    fn integer(x: &str, radix: u32) -> Option<BigInt> {
+        let (negative, digits) = sign(x);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return None;
+        }
+        let n = BigInt::parse_bytes(digits.as_bytes(), radix)?;
+        Some(if negative { -n } else { n })
+    }
+
+    /// Largest exponent magnitude of an exact decimal, whose value would
+    /// otherwise take unbounded time and memory to compute
+    const MAX_EXPONENT: u64 = 4096;
+
+    /// Sign, whole and fraction digits, and unparsed exponent of a decimal
+    /// like `-1.5e3`, checking its syntax without computing its value
+    fn decimal_parts(x: &str) -> Option<(bool, &str, &str, &str)> {
+        let (negative, x) = sign(x);
+        let (mantissa, exponent) = x.split_once(['e', 'E']).unwrap_or((x, "0"));
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits = |x: &str| x.chars().all(|c| c.is_ascii_digit());
+        let (_, exponent_digits) = sign(exponent);
+        let valid = !(whole.is_empty() && fraction.is_empty())
+            && digits(whole)
+            && digits(fraction)
+            && !exponent_digits.is_empty()
+            && digits(exponent_digits);
+        valid.then_some((negative, whole, fraction, exponent))
+    }
+
+    /// Outcome of computing the exact value of a decimal
+    enum Decimal {
+        Value(BigRational),
+        /// Exponent beyond MAX_EXPONENT in magnitude
+        TooLarge,
+        Malformed,
+    }
+
+    /// Exact value of a decimal like `-1.5e3`
+    fn decimal(x: &str) -> Decimal {
+        let Some((negative, whole, fraction, exponent)) = decimal_parts(x) else {
+            return Decimal::Malformed;
+        };
+        let exponent = match integer(exponent, 10).map(|exponent| exponent.to_i64()) {
+            Some(Some(exponent)) if exponent.unsigned_abs() <= MAX_EXPONENT => exponent,
+            Some(_) => return Decimal::TooLarge,
+            None => return Decimal::Malformed,
+        };
+        let digits = format!("{}{}", whole, fraction);
+
+        let Some(n) = BigInt::parse_bytes(digits.as_bytes(), 10) else {
+            return Decimal::Malformed;
+        };
+        let n = if negative { -n } else { n };
+        let scale = exponent - fraction.len() as i64;
+        let Ok(magnitude) = u32::try_from(scale.unsigned_abs()) else {
+            return Decimal::TooLarge;
+        };
+        let power = BigInt::from(10).pow(magnitude);
+        Decimal::Value(match scale < 0 {
+            true => BigRational::new(n, power),
+            false => BigRational::from_integer(n * power),
+        })
+    }
+
+    /// Whether x starts with `-`, and x without its sign
+    fn sign(x: &str) -> (bool, &str) {
+        match x.as_bytes().first() {
+            Some(b'-') => (true, &x[1..]),
+            Some(b'+') => (false, &x[1..]),
+            _ => (false, x),
+        }
     }
 }
@@ -3,16 +3,36 @@ mod error;
 mod eval;
 mod expr;
 mod lexer;
+mod macros;
 mod parser;
+mod pretty;
+mod span;
 
 pub use env::Env;
+pub use error::Backtrace;
+pub use error::Diagnostic;
+pub use error::Frame;
+pub use error::LispError;
+pub use eval::Continuation;
+pub use eval::Control;
 pub use eval::Evaluator;
 pub use lexer::Lexer;
 pub use lexer::Token;
+pub use macros::Alias;
+pub use macros::Macro;
+pub use macros::SyntaxRules;
+pub use macros::Transformer;
 pub use parser::Parser;
+pub use pretty::Printer;
+pub use span::Source;
+pub use span::Span;
+pub use span::SpanTable;
 
 pub use expr::builtins;
 pub use expr::consts;
 pub use expr::intrinsics;
 pub use expr::math;
+pub use expr::Arity;
 pub use expr::Expr;
+pub use expr::ExprField;
+pub use expr::NativeFn;
@@ -0,0 +1,506 @@
+use crate::env::Env;
+use crate::error::LispError;
+use crate::expr::Expr;
+use crate::intrinsics::{dotted_list, elements, list};
+use crate::lexer::Token;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Syntactic binding that rewrites a use `(name forms...)` into another form
+#[derive(Clone)]
+pub struct Macro {
+    pub name: String,
+    pub transformer: Transformer,
+    /// Env the definition was evaluated in
+    pub env: Rc<RefCell<Env>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transformer {
+    /// `(defmacro name (params...) body...)`, a procedure receiving the
+    /// argument forms unevaluated and returning the form to evaluate instead
+    Procedure {
+        params: Vec<String>,
+        /// Parameter bound to the remaining forms, from `(params... . rest)`
+        rest: Option<String>,
+        /// Body list as written in the defmacro
+        body: Expr,
+    },
+    /// `(syntax-rules (literals...) (pattern template)...)`
+    Rules(SyntaxRules),
+}
+
+impl Macro {
+    /// Procedure transformer for the `(params...)` list and body of a defmacro
+    pub fn procedure(
+        name: impl Into<String>,
+        params: Expr,
+        body: Expr,
+        env: Rc<RefCell<Env>>,
+    ) -> anyhow::Result<Self> {
+        let mut names = Vec::new();
+        let mut rest = params;
+        while let Expr::Composed { car, cdr } = rest {
+            names.push(Self::param(&car)?);
+            rest = cdr.into_inner();
+        }
+        let rest = match rest {
+            Expr::Atom(Token::Nil) => None,
+            tail => Some(Self::param(&tail)?),
+        };
+
+        Ok(Self {
+            name: name.into(),
+            transformer: Transformer::Procedure {
+                params: names,
+                rest,
+                body,
+            },
+            env,
+        })
+    }
+
+    /// Pattern transformer for a `(syntax-rules ...)` spec
+    pub fn rules(
+        name: impl Into<String>,
+        spec: Expr,
+        env: Rc<RefCell<Env>>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: name.into(),
+            transformer: Transformer::Rules(SyntaxRules::new(spec)?),
+            env,
+        })
+    }
+
+    fn param(expr: &Expr) -> anyhow::Result<String> {
+        match expr {
+            Expr::Atom(Token::Symbol(sym)) => Ok(sym.clone()),
+            _ => Err(LispError::TypeMismatch {
+                expected: "Token::Symbol",
+                found: expr.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// Env binding the params of a procedure transformer to the argument forms
+    /// of a use, extending the Env of the definition
+    pub fn bind(&self, args: Expr) -> anyhow::Result<Rc<RefCell<Env>>> {
+        let (params, rest_param) = match &self.transformer {
+            Transformer::Procedure { params, rest, .. } => (params, rest),
+            Transformer::Rules(_) => return Err(LispError::NotCallable(self.name.clone()).into()),
+        };
+
+        let mut args = elements(args)?;
+        let rest = match (rest_param, args.len()) {
+            (None, found) if found != params.len() => {
+                return Err(LispError::ArityMismatch {
+                    name: self.name.clone(),
+                    expected: params.len(),
+                    found,
+                }
+                .into())
+            }
+            (Some(_), found) if found < params.len() => {
+                return Err(LispError::TooFewArguments {
+                    name: self.name.clone(),
+                    expected: params.len(),
+                    found,
+                }
+                .into())
+            }
+            _ => args.split_off(params.len()),
+        };
+
+        let env = Rc::new(RefCell::new(Env::extend(self.env.clone())));
+        for (param, arg) in params.iter().zip(args) {
+            env.borrow_mut().set(param, arg);
+        }
+        if let Some(name) = rest_param {
+            env.borrow_mut().set(name, list(rest));
+        }
+        Ok(env)
+    }
+}
+
+/// Macros are equal only if they share their defining Env
+impl PartialEq for Macro {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.transformer == other.transformer
+            && Rc::ptr_eq(&self.env, &other.env)
+    }
+}
+
+/// Skips the defining Env, which may refer back to the macro
+impl fmt::Debug for Macro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Macro")
+            .field("name", &self.name)
+            .field("transformer", &self.transformer)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Symbol a syntax-rules expansion introduced, standing for name as bound in env
+///
+/// Aliases are fresh, so binding one never captures a user variable, and an
+/// unbound alias resolves in the Env of the macro definition.
+#[derive(Clone)]
+pub struct Alias {
+    pub name: String,
+    pub env: Rc<RefCell<Env>>,
+}
+
+impl Alias {
+    /// Separates the name from the serial number of an alias, which cannot
+    /// occur in a symbol read from source
+    pub const MARK: char = ';';
+
+    pub fn is_alias(sym: &str) -> bool {
+        sym.contains(Self::MARK)
+    }
+
+    /// Name a possibly renamed symbol was written as in its template
+    pub fn base(sym: &str) -> &str {
+        sym.split(Self::MARK).next().unwrap_or(sym)
+    }
+
+    /// Fresh symbol for name
+    fn fresh(name: &str) -> String {
+        static SERIAL: AtomicUsize = AtomicUsize::new(0);
+        let serial = SERIAL.fetch_add(1, Ordering::Relaxed);
+        format!("{}{}{}", Self::base(name), Self::MARK, serial)
+    }
+}
+
+impl PartialEq for Alias {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && Rc::ptr_eq(&self.env, &other.env)
+    }
+}
+
+impl fmt::Debug for Alias {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Alias")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Rules of a syntax-rules transformer, tried in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxRules {
+    literals: Vec<String>,
+    /// Patterns without their leading keyword, with their templates
+    rules: Vec<(Expr, Expr)>,
+}
+
+/// Forms a pattern variable matched, nested once per enclosing ellipsis
+#[derive(Debug, Clone)]
+enum Binding {
+    One(Expr),
+    Many(Vec<Binding>),
+}
+
+type Bindings = HashMap<String, Binding>;
+
+/// Where a template form is instantiated
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    Code,
+    /// Inside quote, where symbols are data and keep their names
+    Quote,
+    /// Inside quasiquote, where unquoted forms are code again
+    Quasi,
+}
+
+impl SyntaxRules {
+    const ELLIPSIS: &str = "...";
+
+    /// Parse `(syntax-rules (literals...) (pattern template)...)`
+    pub fn new(spec: Expr) -> anyhow::Result<Self> {
+        let bad = || LispError::BadSyntax(format!("syntax-rules spec {}", spec));
+        let mut parts = elements(spec.clone())?.into_iter();
+        match parts.next() {
+            Some(Expr::Atom(Token::Symbol(sym))) if sym.eq_ignore_ascii_case("syntax-rules") => (),
+            _ => return Err(bad().into()),
+        }
+
+        let literals = elements(parts.next().ok_or_else(bad)?)?
+            .into_iter()
+            .map(|literal| match literal {
+                Expr::Atom(Token::Symbol(sym)) => Ok(sym.to_ascii_lowercase()),
+                _ => Err(bad()),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let rules = parts
+            .map(|rule| match elements(rule)?.as_slice() {
+                [Expr::Composed { cdr, .. }, template] => {
+                    Ok((cdr.as_ref().clone(), template.clone()))
+                }
+                _ => Err(bad().into()),
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { literals, rules })
+    }
+
+    /// Rewrite the args of a use of name by the first rule whose pattern
+    /// matches them
+    ///
+    /// Symbols the template introduces are renamed to fresh aliases, bound in
+    /// env to their meaning in the defining Env def.
+    pub fn expand(
+        &self,
+        name: &str,
+        args: Expr,
+        env: &Rc<RefCell<Env>>,
+        def: &Rc<RefCell<Env>>,
+    ) -> anyhow::Result<Expr> {
+        for (pattern, template) in &self.rules {
+            let mut bindings = Bindings::new();
+            if !self.matches(pattern, &args, &mut bindings) {
+                continue;
+            }
+
+            let mut renames = HashMap::new();
+            let expansion = self.instantiate(template, &bindings, Context::Code, &mut renames)?;
+            for (name, alias) in renames {
+                env.borrow_mut().set_alias(
+                    alias,
+                    Alias {
+                        name,
+                        env: def.clone(),
+                    },
+                );
+            }
+            return Ok(expansion);
+        }
+
+        let form = Expr::new_composed(Expr::Atom(Token::Symbol(name.into())), args);
+        Err(LispError::BadSyntax(format!("no syntax-rules pattern matches {}", form)).into())
+    }
+
+    fn is_literal(&self, sym: &str) -> bool {
+        self.literals
+            .iter()
+            .any(|literal| literal.eq_ignore_ascii_case(sym))
+    }
+
+    fn is_ellipsis(expr: &Expr) -> bool {
+        matches!(expr, Expr::Atom(Token::Symbol(sym)) if sym == Self::ELLIPSIS)
+    }
+
+    /// Elements and tail of a possibly dotted list
+    fn split(expr: &Expr) -> (Vec<Expr>, Expr) {
+        let mut items = Vec::new();
+        let mut rest = expr;
+        while let Expr::Composed { car, cdr } = rest {
+            items.push(car.as_ref().clone());
+            rest = cdr;
+        }
+        (items, rest.clone())
+    }
+
+    /// Match form against pattern, binding its pattern variables
+    fn matches(&self, pattern: &Expr, form: &Expr, bindings: &mut Bindings) -> bool {
+        match pattern {
+            Expr::Atom(Token::Symbol(sym)) if sym == "_" => true,
+            Expr::Atom(Token::Symbol(sym)) if self.is_literal(sym) => {
+                matches!(form, Expr::Atom(Token::Symbol(found)) if Alias::base(found).eq_ignore_ascii_case(sym))
+            }
+            Expr::Atom(Token::Symbol(sym)) => {
+                bindings.insert(sym.to_ascii_lowercase(), Binding::One(form.clone()));
+                true
+            }
+            Expr::Composed { .. } => self.matches_list(pattern, form, bindings),
+            _ => pattern == form,
+        }
+    }
+
+    /// Match `(p... [p ...] p... . tail)` against the elements of form
+    fn matches_list(&self, pattern: &Expr, form: &Expr, bindings: &mut Bindings) -> bool {
+        let (patterns, tail) = Self::split(pattern);
+        let (forms, form_tail) = Self::split(form);
+
+        let Some(at) = patterns.iter().position(Self::is_ellipsis) else {
+            if forms.len() < patterns.len()
+                || (tail == Expr::Atom(Token::Nil) && forms.len() > patterns.len())
+            {
+                return false;
+            }
+            let rest = dotted_list(forms[patterns.len()..].to_vec(), form_tail);
+            return patterns
+                .iter()
+                .zip(&forms)
+                .all(|(pattern, form)| self.matches(pattern, form, bindings))
+                && self.matches(&tail, &rest, bindings);
+        };
+
+        // The pattern before the ellipsis matches as many forms as are left
+        // over by the patterns around it
+        let (before, after) = (&patterns[..at.saturating_sub(1)], &patterns[at + 1..]);
+        if at == 0 || forms.len() < before.len() + after.len() {
+            return false;
+        }
+        let repeated = &patterns[at - 1];
+        let count = forms.len() - before.len() - after.len();
+
+        let mut matched = Vec::new();
+        for form in &forms[before.len()..before.len() + count] {
+            let mut inner = Bindings::new();
+            if !self.matches(repeated, form, &mut inner) {
+                return false;
+            }
+            matched.push(inner);
+        }
+        for var in self.pattern_vars(repeated) {
+            let each = matched
+                .iter_mut()
+                .filter_map(|inner| inner.remove(&var))
+                .collect();
+            bindings.insert(var, Binding::Many(each));
+        }
+
+        before
+            .iter()
+            .zip(&forms)
+            .chain(after.iter().zip(&forms[before.len() + count..]))
+            .all(|(pattern, form)| self.matches(pattern, form, bindings))
+            && self.matches(&tail, &form_tail, bindings)
+    }
+
+    /// Pattern variables bound by pattern
+    fn pattern_vars(&self, pattern: &Expr) -> Vec<String> {
+        match pattern {
+            Expr::Atom(Token::Symbol(sym))
+                if sym != "_" && sym != Self::ELLIPSIS && !self.is_literal(sym) =>
+            {
+                vec![sym.to_ascii_lowercase()]
+            }
+            Expr::Composed { car, cdr } => {
+                let mut vars = self.pattern_vars(car);
+                vars.extend(self.pattern_vars(cdr));
+                vars
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Build template with pattern variables replaced by what they matched
+    /// and other symbols renamed
+    fn instantiate(
+        &self,
+        template: &Expr,
+        bindings: &Bindings,
+        context: Context,
+        renames: &mut HashMap<String, String>,
+    ) -> anyhow::Result<Expr> {
+        let (items, tail) = match template {
+            Expr::Atom(Token::Symbol(sym)) => {
+                return match bindings.get(&sym.to_ascii_lowercase()) {
+                    Some(Binding::One(form)) => Ok(form.clone()),
+                    Some(Binding::Many(_)) => Err(LispError::BadSyntax(format!(
+                        "pattern variable {} used without ellipsis",
+                        sym
+                    ))
+                    .into()),
+                    None if context == Context::Code => Ok(Expr::Atom(Token::Symbol(
+                        renames
+                            .entry(sym.to_ascii_lowercase())
+                            .or_insert_with(|| Alias::fresh(sym))
+                            .clone(),
+                    ))),
+                    None => Ok(template.clone()),
+                }
+            }
+            Expr::Composed { .. } => Self::split(template),
+            _ => return Ok(template.clone()),
+        };
+
+        let inner = match (&items[0], context) {
+            (Expr::Atom(Token::Symbol(sym)), Context::Code) if sym == "quote" => Context::Quote,
+            (Expr::Atom(Token::Symbol(sym)), Context::Code) if sym == "quasiquote" => {
+                Context::Quasi
+            }
+            (Expr::Atom(Token::Symbol(sym)), Context::Quasi)
+                if sym == "unquote" || sym == "unquote-splicing" =>
+            {
+                Context::Code
+            }
+            _ => context,
+        };
+        let mut exprs = Vec::new();
+        let mut items = items.into_iter().peekable();
+        if inner != context {
+            // Keywords of quote forms keep their names, the evaluator matches them
+            exprs.extend(items.next());
+        }
+        while let Some(item) = items.next() {
+            let mut depth = 0;
+            while items.next_if(Self::is_ellipsis).is_some() {
+                depth += 1;
+            }
+            exprs.extend(self.repeat(&item, depth, bindings, inner, renames)?);
+        }
+        let tail = self.instantiate(&tail, bindings, inner, renames)?;
+        Ok(dotted_list(exprs, tail))
+    }
+
+    /// Instantiate template once per form its pattern variables matched,
+    /// for each of depth ellipses following it
+    fn repeat(
+        &self,
+        template: &Expr,
+        depth: usize,
+        bindings: &Bindings,
+        context: Context,
+        renames: &mut HashMap<String, String>,
+    ) -> anyhow::Result<Vec<Expr>> {
+        if depth == 0 {
+            return Ok(vec![self.instantiate(template, bindings, context, renames)?]);
+        }
+
+        let repeated = self
+            .pattern_vars(template)
+            .into_iter()
+            .filter_map(|var| match bindings.get(&var) {
+                Some(Binding::Many(each)) => Some((var, each)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let count = match repeated.first() {
+            Some((_, each)) => each.len(),
+            None => {
+                return Err(LispError::BadSyntax(format!(
+                    "no pattern variable to repeat in {}",
+                    template
+                ))
+                .into())
+            }
+        };
+        if repeated.iter().any(|(_, each)| each.len() != count) {
+            return Err(LispError::BadSyntax(format!(
+                "pattern variables of {} repeat unequally",
+                template
+            ))
+            .into());
+        }
+
+        let mut exprs = Vec::new();
+        for i in 0..count {
+            let mut inner = bindings.clone();
+            for (var, each) in &repeated {
+                inner.insert(var.clone(), each[i].clone());
+            }
+            exprs.extend(self.repeat(template, depth - 1, &inner, context, renames)?);
+        }
+        Ok(exprs)
+    }
+}
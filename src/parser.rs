@@ -1,59 +1,197 @@
 use crate::consts::*;
+use crate::error::LispError;
 use crate::expr::Expr;
 use crate::intrinsics::*;
 use crate::lexer::Lexer;
 use crate::lexer::Token;
+use crate::span::{Source, Span, SpanTable};
 use std::collections::VecDeque;
 use std::convert::AsRef;
 use std::path::Path;
+use std::sync::Arc;
 
 pub struct Parser;
 
 impl Parser {
-    pub fn parse_file(path: impl AsRef<Path>) -> anyhow::Result<Expr> {
-        let mut tokens = Lexer::tokenize_file(path)?;
-        Self::parse_tokens(&mut tokens)
+    /// Parse every top-level form of a file in order
+    pub fn parse_file(path: impl AsRef<Path>) -> anyhow::Result<Vec<Expr>> {
+        Ok(Self::parse_spanned(Lexer::read_file(path)?)?.0)
     }
 
+    /// Parse exactly one form
     pub fn parse(source: impl AsRef<str>) -> anyhow::Result<Expr> {
-        let mut tokens = Lexer::tokenize(source)?;
-        Self::parse_tokens(&mut tokens)
+        Self::parse_single(Source::new("<input>", source.as_ref()))
     }
 
-    fn parse_tokens(tokens: &mut VecDeque<Token>) -> anyhow::Result<Expr> {
-        let token = tokens.pop_front();
+    /// Parse every top-level form in order
+    pub fn parse_program(source: impl AsRef<str>) -> anyhow::Result<Vec<Expr>> {
+        Ok(Self::parse_spanned(Source::new("<input>", source.as_ref()))?.0)
+    }
+
+    /// Parse every top-level form of a Source, recording the Span of every Expr node in a
+    /// SpanTable
+    pub fn parse_spanned(source: Arc<Source>) -> anyhow::Result<(Vec<Expr>, SpanTable)> {
+        let mut tokens = Lexer::tokenize_spanned(source)?;
+        let mut spans = SpanTable::new();
+        let mut exprs = Vec::new();
 
-        if token != Some(Token::LParen) {
-            panic!("Expect Token::LParen");
+        while let Some((token, span)) = tokens.front() {
+            if *token == Token::RParen {
+                return Err(LispError::UnexpectedToken(Token::RParen).at(span.clone()));
+            }
+            let (expr, span) = Self::parse_datum(&mut tokens, &mut spans)?;
+            spans.insert_form(span);
+            exprs.push(expr);
         }
 
-        let mut expr = NIL;
+        Ok((exprs, spans))
+    }
 
-        while !tokens.is_empty() {
-            let token = tokens.pop_front().unwrap();
+    fn parse_single(source: Arc<Source>) -> anyhow::Result<Expr> {
+        let mut tokens = Lexer::tokenize_spanned(source)?;
+        if tokens.is_empty() {
+            return Err(LispError::MissingDatum.into());
+        }
 
-            match token {
+        let (expr, _) = Self::parse_datum(&mut tokens, &mut SpanTable::new())?;
+        match tokens.pop_front() {
+            Some((token, span)) => Err(LispError::UnexpectedToken(token).at(span)),
+            None => Ok(expr),
+        }
+    }
+
+    /// Parse a list or an atom, expanding quote shorthands
+    ///
+    /// Lists and quotes still waiting for a datum are kept on a stack of their
+    /// own, so nesting depth is not limited by the Rust stack.
+    fn parse_datum(
+        tokens: &mut VecDeque<(Token, Span)>,
+        spans: &mut SpanTable,
+    ) -> anyhow::Result<(Expr, Span)> {
+        let mut open = Vec::new();
+
+        loop {
+            let (token, span) = match open.last() {
+                Some(Open::List { start, .. }) => tokens
+                    .pop_front()
+                    .ok_or_else(|| LispError::UnbalancedParens.at(start.clone()))?,
+                _ => tokens.pop_front().ok_or(LispError::UnbalancedParens)?,
+            };
+
+            // `(a b . c)` takes exactly one datum after the dot
+            if let Some(Open::List { tail: Some(_), .. }) = open.last() {
+                if token != Token::RParen {
+                    return Err(LispError::UnexpectedToken(token).at(span));
+                }
+            }
+            if let Some(keyword) = Self::shorthand(&token) {
+                if tokens.is_empty() {
+                    return Err(LispError::MissingDatum.at(span));
+                }
+                open.push(Open::Quote { keyword, span });
+                continue;
+            }
+
+            let mut datum = match token {
                 Token::LParen => {
-                    tokens.push_front(Token::LParen);
-                    let sub_expr = Self::parse_tokens(tokens)?;
-                    expr = append(expr, sub_expr);
+                    open.push(Open::List {
+                        start: span,
+                        exprs: Vec::new(),
+                        elements: Vec::new(),
+                        dotted: false,
+                        tail: None,
+                    });
+                    continue;
                 }
-                Token::RParen => return Ok(expr),
-                Token::Lambda => expr = append(expr, LAMBDA),
-                Token::Apply => expr = append(expr, APPLY),
-                Token::Define => expr = append(expr, DEFINE),
-                Token::Cond => expr = append(expr, COND),
-                Token::True => expr = append(expr, TRUE),
-                Token::False => expr = append(expr, FALSE),
-                Token::Nil => expr = append(expr, NIL),
-                Token::Integer(n) => expr = append(expr, Expr::new_atom(Token::Integer(n))),
-                Token::Symbol(ref sym) => {
-                    expr = append(expr, Expr::new_atom(Token::Symbol(sym.into())))
+                Token::Dot => match open.last_mut() {
+                    Some(Open::List { exprs, dotted, .. }) if !exprs.is_empty() && !*dotted => {
+                        *dotted = true;
+                        continue;
+                    }
+                    _ => return Err(LispError::UnexpectedToken(Token::Dot).at(span)),
+                },
+                Token::RParen => match open.pop() {
+                    Some(Open::List {
+                        start,
+                        exprs,
+                        elements,
+                        dotted,
+                        tail,
+                    }) if dotted == tail.is_some() => {
+                        let expr = dotted_list(exprs, tail.unwrap_or(NIL));
+                        spans.insert_elements(&expr, elements);
+                        let span = start.to(&span);
+                        spans.insert(expr.clone(), span.clone());
+                        (expr, span)
+                    }
+                    _ => return Err(LispError::UnexpectedToken(Token::RParen).at(span)),
+                },
+                token => (Expr::new_atom(token), span),
+            };
+
+            // Hand the datum to the innermost list or quote waiting for one
+            loop {
+                match open.pop() {
+                    None => return Ok(datum),
+                    Some(Open::Quote { keyword, span }) => {
+                        let (expr, datum_span) = datum;
+                        let expr = list(vec![Expr::new_atom(Token::Symbol(keyword.into())), expr]);
+                        spans.insert_elements(&expr, [span.clone(), datum_span.clone()]);
+                        let span = span.to(&datum_span);
+                        spans.insert(expr.clone(), span.clone());
+                        datum = (expr, span);
+                    }
+                    Some(Open::List {
+                        start,
+                        mut exprs,
+                        mut elements,
+                        dotted,
+                        mut tail,
+                    }) => {
+                        match dotted {
+                            true => tail = Some(datum.0),
+                            false => {
+                                exprs.push(datum.0);
+                                elements.push(datum.1);
+                            }
+                        }
+                        open.push(Open::List {
+                            start,
+                            exprs,
+                            elements,
+                            dotted,
+                            tail,
+                        });
+                        break;
+                    }
                 }
-                #[allow(unreachable_patterns)]
-                _ => unimplemented!("Unimplemented Token::{:?}", token),
             }
         }
-        panic!("Expect Token::RParen");
     }
+
+    /// Keyword a quote shorthand Token stands for
+    fn shorthand(token: &Token) -> Option<&'static str> {
+        match token {
+            Token::Quote => Some("quote"),
+            Token::Quasiquote => Some("quasiquote"),
+            Token::Unquote => Some("unquote"),
+            Token::UnquoteSplicing => Some("unquote-splicing"),
+            _ => None,
+        }
+    }
+}
+
+/// List or quote shorthand waiting for a datum while parsing
+enum Open {
+    /// List opened at start, with the elements read so far and their Spans
+    List {
+        start: Span,
+        exprs: Vec<Expr>,
+        elements: Vec<Span>,
+        /// Whether a dot was read, after which tail is the only datum
+        dotted: bool,
+        tail: Option<Expr>,
+    },
+    /// Quote shorthand at span, read as `(keyword datum)`
+    Quote { keyword: &'static str, span: Span },
 }
@@ -0,0 +1,231 @@
+use crate::expr::Expr;
+use crate::lexer::Token;
+
+pub struct Printer;
+
+/// Expr with the width it prints flat in, measured once for the whole tree
+///
+/// Nodes refer to their items by index into the measured tree, so deep
+/// nesting needs no recursion to build or free it.
+struct Measured<'a> {
+    expr: &'a Expr,
+    width: usize,
+    /// Elements of a list, empty for anything else
+    items: Vec<usize>,
+    /// Tail of a dotted list
+    tail: Option<usize>,
+}
+
+/// Step of laying out an Expr
+enum Task {
+    /// Lay out a measured node positioned at a column
+    Layout(usize, usize),
+    Text(&'static str),
+    /// Start a new line indented this far
+    Newline(usize),
+}
+
+/// List whose items are being measured
+struct Open<'a> {
+    expr: &'a Expr,
+    /// Items left to measure, next one last, the tail of a dotted list first
+    pending: Vec<&'a Expr>,
+    items: Vec<usize>,
+    dotted: bool,
+}
+
+impl Printer {
+    /// Line width used when none is given
+    pub const DEFAULT_WIDTH: usize = 80;
+
+    /// Lay out expr within width columns using Lisp indentation conventions
+    ///
+    /// Forms that fit on the remaining line print flat. Otherwise `define`, `defmacro`,
+    /// `lambda` and `let` bodies indent by 2, `cond` clauses line up under the first clause
+    /// and call arguments line up under the first argument. Data lists fill lines
+    /// with elements aligned under their first element.
+    pub fn pretty(expr: &Expr, width: usize) -> String {
+        let nodes = Self::measure(expr);
+        let mut out = String::new();
+        let mut tasks = vec![Task::Layout(nodes.len() - 1, 0)];
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Layout(node, column) => {
+                    let steps = Self::layout(&nodes, node, column, width);
+                    match steps.is_empty() {
+                        true => out.push_str(&nodes[node].expr.to_string()),
+                        false => tasks.extend(steps.into_iter().rev()),
+                    }
+                }
+                Task::Text(text) => out.push_str(text),
+                Task::Newline(indent) => Self::newline(indent, &mut out),
+            }
+        }
+        out
+    }
+
+    /// Steps laying out node at column, none if it prints flat
+    fn layout(nodes: &[Measured], node: usize, column: usize, width: usize) -> Vec<Task> {
+        let Measured { items, tail, .. } = &nodes[node];
+        if column + nodes[node].width <= width || items.is_empty() {
+            return Vec::new();
+        }
+
+        let head = nodes[items[0]].width;
+        let (inline, indent) = match nodes[items[0]].expr {
+            Expr::Atom(Token::Define | Token::Lambda) => (2, column + 2),
+            // Named let keeps its name and bindings on the first line
+            Expr::Atom(Token::Symbol(sym)) if sym == "let" && items.len() > 2 => {
+                match nodes[items[1]].expr {
+                    Expr::Atom(Token::Symbol(_)) => (3, column + 2),
+                    _ => (2, column + 2),
+                }
+            }
+            // defmacro keeps its name and parameters on the first line
+            Expr::Atom(Token::Symbol(sym)) if sym == "defmacro" => (3, column + 2),
+            Expr::Atom(Token::Symbol(sym))
+                if matches!(
+                    sym.as_str(),
+                    "let*" | "letrec" | "let-syntax" | "define-syntax"
+                ) =>
+            {
+                (2, column + 2)
+            }
+            Expr::Atom(Token::Cond) => (2, column + head + 2),
+            Expr::Atom(Token::Symbol(_) | Token::Apply) if items.len() > 1 => {
+                (2, column + head + 2)
+            }
+            _ => (1, column + 1),
+        };
+
+        // Items sharing the first line with the opening paren
+        let mut steps = vec![Task::Text("(")];
+        let mut at = column + 1;
+        for (i, &item) in items.iter().take(inline).enumerate() {
+            if i > 0 {
+                steps.push(Task::Text(" "));
+                at += 1;
+            }
+            steps.push(Task::Layout(item, at));
+            at += nodes[item].width + 1;
+        }
+
+        // Data lists fill each line, forms put every remaining item on its own line
+        let fill = inline == 1;
+        for &item in items.iter().skip(inline) {
+            let len = nodes[item].width;
+            if fill && at + len < width {
+                steps.push(Task::Text(" "));
+                steps.push(Task::Layout(item, at));
+                at += len + 1;
+            } else {
+                steps.push(Task::Newline(indent));
+                steps.push(Task::Layout(item, indent));
+                // Nothing follows an item that spans several lines
+                at = if indent + len <= width {
+                    indent + len + 1
+                } else {
+                    width
+                };
+            }
+        }
+
+        if let Some(tail) = *tail {
+            if fill && at + nodes[tail].width + 2 < width {
+                steps.push(Task::Text(" . "));
+                steps.push(Task::Layout(tail, at + 2));
+            } else {
+                steps.push(Task::Newline(indent));
+                steps.push(Task::Text(". "));
+                steps.push(Task::Layout(tail, indent + 2));
+            }
+        }
+        steps.push(Task::Text(")"));
+        steps
+    }
+
+    fn newline(indent: usize, out: &mut String) {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+    }
+
+    /// Flat width of expr and of every subexpression, so layout never prints
+    /// a subtree just to measure it
+    ///
+    /// Items are measured before the list holding them, expr comes last.
+    fn measure(expr: &Expr) -> Vec<Measured<'_>> {
+        let mut nodes = Vec::new();
+        let mut open: Vec<Open> = Vec::new();
+        let mut next = expr;
+
+        loop {
+            let (elements, tail) = Self::split(next);
+            if !elements.is_empty() {
+                let mut pending = Vec::from_iter(tail);
+                pending.extend(elements.into_iter().rev());
+                let list = next;
+                next = pending.pop().unwrap_or(list);
+                open.push(Open {
+                    expr: list,
+                    pending,
+                    items: Vec::new(),
+                    dotted: tail.is_some(),
+                });
+                continue;
+            }
+            nodes.push(Measured {
+                expr: next,
+                width: next.to_string().chars().count(),
+                items: Vec::new(),
+                tail: None,
+            });
+
+            // Hand the measured node to the lists it completes
+            loop {
+                let done = nodes.len() - 1;
+                let Some(list) = open.last_mut() else {
+                    return nodes;
+                };
+                list.items.push(done);
+                if let Some(item) = list.pending.pop() {
+                    next = item;
+                    break;
+                }
+
+                let (expr, dotted) = (list.expr, list.dotted);
+                let mut items = std::mem::take(&mut list.items);
+                open.pop();
+                let tail = if dotted { items.pop() } else { None };
+                // Opening paren, each item followed by a space or the closing
+                // paren, and ` . tail`
+                let width = 1
+                    + items
+                        .iter()
+                        .map(|&item| nodes[item].width + 1)
+                        .sum::<usize>()
+                    + tail.map_or(0, |tail| nodes[tail].width + 3);
+                nodes.push(Measured {
+                    expr,
+                    width,
+                    items,
+                    tail,
+                });
+            }
+        }
+    }
+
+    /// Elements of a list, and its tail if it is dotted
+    fn split(expr: &Expr) -> (Vec<&Expr>, Option<&Expr>) {
+        let mut elements = Vec::new();
+        let mut rest = expr;
+        while let Expr::Composed { car, cdr } = rest {
+            elements.push(car.as_ref());
+            rest = cdr;
+        }
+        match rest {
+            _ if elements.is_empty() => (elements, None),
+            Expr::Atom(Token::Nil) => (elements, None),
+            _ => (elements, Some(rest)),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::expr::Expr;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Named source text shared by every Span pointing into it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.into(),
+            text: text.into(),
+        })
+    }
+
+    /// Text of 1-based line number
+    pub fn line(&self, line: usize) -> &str {
+        self.text.lines().nth(line - 1).unwrap_or_default()
+    }
+}
+
+/// Location of a Token or Expr in its Source
+///
+/// `line` and `column` are 1-based, `range` is in bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub source: Arc<Source>,
+    pub line: usize,
+    pub column: usize,
+    pub range: Range<usize>,
+}
+
+impl Span {
+    /// Span covering both self and other
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            range: self.range.start..other.range.end,
+            ..self.clone()
+        }
+    }
+}
+
+/// Side table from parsed Expr nodes to their Span
+///
+/// Lists are keyed by the identity of their first cell, so repeated forms
+/// keep their own Span. Atoms have no identity and are located by position,
+/// as the element of an enclosing list.
+#[derive(Debug, Default)]
+pub struct SpanTable {
+    /// Each list with its Span, holding its cells so their addresses are not
+    /// reused while the table lives
+    lists: HashMap<Cell, (Expr, Span)>,
+    /// Span of the car of every cell of a list
+    elements: HashMap<Cell, Span>,
+    /// Span of each top-level form, in order
+    forms: Vec<Span>,
+}
+
+/// Identity of a Composed node
+type Cell = (*const Expr, *const Expr);
+
+fn cell(expr: &Expr) -> Option<Cell> {
+    match expr {
+        Expr::Composed { car, cdr } => Some((car.as_ptr(), cdr.as_ptr())),
+        _ => None,
+    }
+}
+
+impl SpanTable {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Record the Span of a list, atoms are only recorded as elements
+    pub fn insert(&mut self, expr: Expr, span: Span) {
+        if let Some(key) = cell(&expr) {
+            self.lists.insert(key, (expr, span));
+        }
+    }
+
+    /// Record the Span of the next top-level form
+    pub fn insert_form(&mut self, span: Span) {
+        self.forms.push(span);
+    }
+
+    /// Spans of the top-level forms, in order
+    pub fn forms(&self) -> &[Span] {
+        &self.forms
+    }
+
+    /// Record the Span of each element of list, in order
+    pub fn insert_elements(&mut self, list: &Expr, spans: impl IntoIterator<Item = Span>) {
+        let mut rest = list;
+        for span in spans {
+            match (cell(rest), rest) {
+                (Some(key), Expr::Composed { cdr, .. }) => {
+                    self.elements.insert(key, span);
+                    rest = cdr;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    pub fn get(&self, expr: &Expr) -> Option<&Span> {
+        let key = cell(expr)?;
+        self.lists.get(&key).map(|(_, span)| span)
+    }
+
+    /// Span of atom as the car of list's first cell, or within that car
+    pub fn find_element(&self, atom: &Expr, list: &Expr) -> Option<&Span> {
+        match list {
+            Expr::Composed { car, .. } if car.as_ref() == atom => self.elements.get(&cell(list)?),
+            Expr::Composed { car, .. } => self.find(atom, car),
+            _ => None,
+        }
+    }
+
+    /// Span of the first occurrence of atom within list, depth first
+    pub fn find(&self, atom: &Expr, list: &Expr) -> Option<&Span> {
+        let mut pending = vec![list];
+        while let Some(expr) = pending.pop() {
+            if let Expr::Composed { car, cdr } = expr {
+                if car.as_ref() == atom {
+                    if let Some(span) = cell(expr).and_then(|key| self.elements.get(&key)) {
+                        return Some(span);
+                    }
+                }
+                pending.push(cdr);
+                pending.push(car);
+            }
+        }
+        None
+    }
+}
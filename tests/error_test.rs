@@ -0,0 +1,296 @@
+use lisp::{Arity, Backtrace, Diagnostic, Env, Evaluator, LispError, Parser, Source, Token};
+use std::{cell::RefCell, rc::Rc};
+
+fn eval_err(source: &str) -> LispError {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    Evaluator::eval(source, &mut env)
+        .unwrap_err()
+        .downcast::<LispError>()
+        .unwrap()
+}
+
+#[test]
+fn unbound_symbol_test() {
+    assert_eq!(eval_err("(+ x 1)"), LispError::UnboundSymbol("x".into()));
+    assert_eq!(
+        eval_err("(apply FOO 1)"),
+        LispError::UnboundSymbol("FOO".into())
+    );
+}
+
+#[test]
+fn bad_call_test() {
+    assert_eq!(
+        eval_err("(FOO 1 2)"),
+        LispError::UnboundSymbol("FOO".into())
+    );
+    assert_eq!(eval_err("(1 2)"), LispError::NotCallable("1".into()));
+    assert_eq!(
+        eval_err("((cons 1 2) 3)"),
+        LispError::NotCallable("(1 . 2)".into())
+    );
+    assert_eq!(
+        eval_err("(call/cc (lambda (k) (k 1 2)))"),
+        LispError::ArityMismatch {
+            name: "k".into(),
+            expected: 1,
+            found: 2,
+        }
+    );
+    assert_eq!(
+        eval_err("(car 1 2)"),
+        LispError::ArityMismatch {
+            name: "car".into(),
+            expected: 1,
+            found: 2,
+        }
+    );
+}
+
+#[test]
+fn native_error_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    env.borrow_mut()
+        .define_native("fail", Arity::AtLeast(1), |args| {
+            Err(LispError::BadSyntax(format!("{} arguments", args.len())).into())
+        });
+
+    let err = Evaluator::eval("(cons 1 (fail 2 3))", &mut env).unwrap_err();
+    assert!(err.is::<Diagnostic>());
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::BadSyntax("2 arguments".into())
+    );
+    assert_eq!(
+        Evaluator::eval("(fail)", &mut env)
+            .unwrap_err()
+            .downcast::<LispError>()
+            .unwrap(),
+        LispError::TooFewArguments {
+            name: "fail".into(),
+            expected: 1,
+            found: 0,
+        }
+    );
+}
+
+#[test]
+fn bad_let_test() {
+    assert_eq!(
+        eval_err("(let ((x 1 2)) x)"),
+        LispError::BadSyntax("let binding (x 1 2)".into())
+    );
+    assert_eq!(
+        eval_err("(letrec ((x 1)))"),
+        LispError::BadSyntax("letrec without body".into())
+    );
+}
+
+#[test]
+fn division_by_zero_test() {
+    assert_eq!(eval_err("(/ 1 (- 2 2))"), LispError::DivisionByZero);
+    assert_eq!(eval_err("(/ 0)"), LispError::DivisionByZero);
+    assert_eq!(eval_err("(/ 1.5 0)"), LispError::DivisionByZero);
+}
+
+#[test]
+fn arity_mismatch_test() {
+    assert_eq!(
+        eval_err("(cons (define ID (lambda (x) x)) (apply ID 1 2))"),
+        LispError::ArityMismatch {
+            name: "ID".into(),
+            expected: 1,
+            found: 2,
+        }
+    );
+}
+
+#[test]
+fn type_mismatch_test() {
+    assert!(matches!(
+        eval_err("(< 1 2 t)"),
+        LispError::TypeMismatch { .. }
+    ));
+    assert!(matches!(
+        eval_err("(inexact->exact +inf.0)"),
+        LispError::TypeMismatch { .. }
+    ));
+    assert!(matches!(
+        eval_err("(+ 1 t)"),
+        LispError::TypeMismatch { .. }
+    ));
+    let depth = 50_000;
+    let nested = format!("(+ 1 '{}1{})", "(".repeat(depth), ")".repeat(depth));
+    assert!(matches!(eval_err(&nested), LispError::TypeMismatch { .. }));
+}
+
+#[test]
+fn unbalanced_parens_test() {
+    let err = Parser::parse("(+ 1 (- 2 3)").unwrap_err();
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::UnbalancedParens
+    );
+    let err = Parser::parse("(car x) y").unwrap_err();
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::UnexpectedToken(Token::Symbol("y".into()))
+    );
+    let err = Parser::parse_program("(car x))").unwrap_err();
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::UnexpectedToken(Token::RParen)
+    );
+}
+
+#[test]
+fn diagnostic_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let err =
+        Evaluator::eval_source(Source::new("<test>", "(cons 1\n  (+ 2 y))"), &mut env).unwrap_err();
+    let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+    assert_eq!((diagnostic.span.line, diagnostic.span.column), (2, 8));
+    assert_eq!(
+        diagnostic.to_string(),
+        "error: symbol `y` not defined\n --> <test>:2:8\n  |\n2 |   (+ 2 y))\n  |        ^"
+    );
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::UnboundSymbol("y".into())
+    );
+
+    // Repeated forms and atoms keep their own location
+    for (source, at) in [
+        ("(define x 1)\n(+ x 1)\n(define x 'a)\n(+ x 1)", (4, 1)),
+        ("(let ((y 1)) y)\n(cons 1\n  y)", (3, 3)),
+        (
+            "(define G (lambda (x) (+ x yy)))\n(define H (lambda () yy))\n(H)",
+            (2, 22),
+        ),
+        ("'zz\nzz", (2, 1)),
+    ] {
+        let err = Evaluator::eval_source(Source::new("<test>", source), &mut env).unwrap_err();
+        let span = &err.downcast_ref::<Diagnostic>().unwrap().span;
+        assert_eq!((span.line, span.column), at, "{}", source);
+    }
+
+    // Errors in a syntax-rules expansion point at the macro use
+    let err = Evaluator::eval_source(
+        Source::new(
+            "<test>",
+            "(define-syntax PAIR (syntax-rules () ((_ a b) (list a b))))\n(cons 1\n  (PAIR 1 2))",
+        ),
+        &mut env,
+    )
+    .unwrap_err();
+    let span = &err.downcast_ref::<Diagnostic>().unwrap().span;
+    assert_eq!((span.line, span.column), (3, 3));
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::UnboundSymbol("list".into())
+    );
+    let err = Evaluator::eval_source(
+        Source::new("<test>", "(define G (lambda ()\n  (PAIR 1 2)))\n(G)"),
+        &mut env,
+    )
+    .unwrap_err();
+    let span = &err.downcast_ref::<Diagnostic>().unwrap().span;
+    assert_eq!((span.line, span.column), (2, 3));
+}
+
+#[test]
+fn backtrace_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let err = Evaluator::eval(
+        "(cons (define SUM (lambda (x) (cond ((eq x 0) (/ 1 x)) (t (+ x (apply SUM (- x 1))))))) (apply SUM 2))",
+        &mut env,
+    )
+    .unwrap_err();
+    let backtrace = err.downcast_ref::<Backtrace>().unwrap();
+    assert_eq!(
+        backtrace
+            .frames
+            .iter()
+            .map(|frame| (frame.name.as_str(), frame.args.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("SUM", vec!["0".to_string()]),
+            ("SUM", vec!["1".to_string()]),
+            ("SUM", vec!["2".to_string()]),
+        ]
+    );
+    assert_eq!(backtrace.frames[2].span.as_ref().unwrap().column, 89);
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::DivisionByZero
+    );
+}
+
+#[test]
+fn stack_overflow_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let eval = |source: &str, env: &mut Rc<RefCell<Env>>| {
+        Evaluator::eval_source_limited(Source::new("<test>", source), env, 100)
+    };
+    eval(
+        "(define DEPTH (lambda (n) (if (= n 0) 0 (+ 1 (DEPTH (- n 1))))))",
+        &mut env,
+    )
+    .unwrap();
+
+    let err = eval("(DEPTH 1000)", &mut env).unwrap_err();
+    assert!(err.is::<Diagnostic>());
+    let backtrace = err.downcast_ref::<Backtrace>().unwrap();
+    let printed = backtrace.to_string();
+    let (_, frames) = printed.split_once("backtrace:").unwrap();
+    assert_eq!(frames.lines().count(), 2 * Backtrace::SHOWN + 2);
+    assert!(printed.contains(&format!(
+        "... {} frames elided",
+        backtrace.frames.len() - 2 * Backtrace::SHOWN
+    )));
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::StackOverflow
+    );
+    assert_eq!(eval("(DEPTH 10)", &mut env).unwrap().to_string(), "10");
+}
+
+#[test]
+fn bad_macro_test() {
+    assert_eq!(
+        eval_err("(defmacro 1 (x) x)"),
+        LispError::BadSyntax("defmacro without name and parameters".into())
+    );
+    assert_eq!(
+        eval_err("(defmacro TWICE (x) `(cons ,x ,x)) (TWICE 1 2)"),
+        LispError::ArityMismatch {
+            name: "TWICE".into(),
+            expected: 1,
+            found: 2,
+        }
+    );
+    assert_eq!(
+        eval_err("(defmacro WHEN (test . body) test) (WHEN)"),
+        LispError::TooFewArguments {
+            name: "WHEN".into(),
+            expected: 1,
+            found: 0,
+        }
+    );
+}
+
+#[test]
+fn bad_syntax_rules_test() {
+    assert_eq!(
+        eval_err("(define-syntax TWICE (syntax-rules () ((_ x) (cons x x)))) (TWICE 1 2)"),
+        LispError::BadSyntax("no syntax-rules pattern matches (TWICE 1 2)".into())
+    );
+    assert_eq!(
+        eval_err("(define-syntax ALL (syntax-rules () ((_ x ...) (cons x 1)))) (ALL 1 2)"),
+        LispError::BadSyntax("pattern variable x used without ellipsis".into())
+    );
+    assert_eq!(
+        eval_err("(define-syntax BAD (lambda (x) x))"),
+        LispError::BadSyntax("syntax-rules spec (lambda (x) x)".into())
+    );
+}
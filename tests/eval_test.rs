@@ -1,4 +1,4 @@
-use lisp::{consts::*, Env, Evaluator, Expr, Token};
+use lisp::{consts::*, Arity, Env, Evaluator, Expr, Token};
 use std::{cell::RefCell, rc::Rc};
 
 #[test]
@@ -62,3 +62,637 @@ fn rec_test() {
         Expr::new_composed(NIL, Expr::Atom(Token::Integer(276)))
     );
 }
+
+#[test]
+fn string_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+        Evaluator::eval(
+            "(cons (define GREET (lambda (x) x)) (apply GREET \"hello (world)\"))",
+            &mut env
+        )
+        .unwrap(),
+        Expr::new_composed(NIL, Expr::new_atom(Token::String("hello (world)".into())))
+    );
+}
+
+#[test]
+fn quasiquote_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+        Evaluator::eval("(cons (define X 5) `(1 ,X ,@'(2 3) 4))", &mut env).unwrap(),
+        Expr::new_composed(NIL, Evaluator::eval("'(1 5 2 3 4)", &mut env).unwrap())
+    );
+    assert_eq!(
+        Evaluator::eval("`(1 `(2 ,(3 ,X)))", &mut env).unwrap(),
+        Evaluator::eval("'(1 (quasiquote (2 (unquote (3 5)))))", &mut env).unwrap()
+    );
+}
+
+#[test]
+fn program_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+        Evaluator::eval(
+            "(define SQR (lambda (x) (* x x)))\n(define Y 7)\n(apply SQR Y)",
+            &mut env
+        )
+        .unwrap(),
+        Expr::new_atom(Token::Integer(49))
+    );
+    assert_eq!(Evaluator::eval("", &mut env).unwrap(), NIL);
+}
+
+#[test]
+fn list_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+        Evaluator::eval("(car '(1 2 3))", &mut env).unwrap(),
+        Expr::new_atom(Token::Integer(1))
+    );
+    assert_eq!(
+        Evaluator::eval("(cdr '(1 2 3))", &mut env).unwrap(),
+        Evaluator::eval("'(2 3)", &mut env).unwrap()
+    );
+    assert_eq!(
+        Evaluator::eval("(car '((1 2) 3))", &mut env).unwrap(),
+        Evaluator::eval("'(1 2)", &mut env).unwrap()
+    );
+    assert_eq!(
+        Evaluator::eval("(null (cdr '(1)))", &mut env).unwrap(),
+        TRUE
+    );
+    assert_eq!(
+        Evaluator::eval("(cons 1 '(2))", &mut env).unwrap(),
+        Evaluator::eval("'(1 2)", &mut env).unwrap()
+    );
+}
+
+#[test]
+fn closure_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+        Evaluator::eval(
+            "(define X 1)
+             (define GET-X (lambda () X))
+             (define SHADOW (lambda (x) (apply GET-X)))
+             (apply SHADOW 2)",
+            &mut env
+        )
+        .unwrap(),
+        Expr::new_atom(Token::Integer(1))
+    );
+    assert_eq!(
+        Evaluator::eval(
+            "(define MAKE-COUNTER (lambda () (define N 0) (lambda () (set! N (+ N 1)) N)))
+             (define C1 (apply MAKE-COUNTER))
+             (define C2 (apply MAKE-COUNTER))
+             (apply C1) (apply C1) (apply C2)
+             (cons (apply C1) (apply C2))",
+            &mut env
+        )
+        .unwrap(),
+        Expr::new_composed(
+            Expr::new_atom(Token::Integer(3)),
+            Expr::new_atom(Token::Integer(2))
+        )
+    );
+    assert_eq!(
+        Evaluator::eval(
+            "(define ADDER (lambda (x) (lambda (y) (+ x y))))
+             (apply (apply ADDER 40) 2)",
+            &mut env
+        )
+        .unwrap(),
+        Expr::new_atom(Token::Integer(42))
+    );
+    assert_eq!(
+        Evaluator::eval("(lambda (a b) a)", &mut env)
+            .unwrap()
+            .to_string(),
+        "#<lambda (a b)>"
+    );
+}
+
+#[test]
+fn direct_call_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+        Evaluator::eval("((lambda (x) x) 5)", &mut env).unwrap(),
+        Expr::new_atom(Token::Integer(5))
+    );
+    assert_eq!(
+        Evaluator::eval(
+            "(define SUB (lambda (x y) (- x y)))
+             (define TWICE (lambda (g x) (g (g x))))
+             (TWICE (lambda (x) (SUB x 1)) (SUB 10 3))",
+            &mut env
+        )
+        .unwrap(),
+        Expr::new_atom(Token::Integer(5))
+    );
+    assert_eq!(
+        Evaluator::eval("(((lambda (x) (lambda (y) (cons x y))) 1) 2)", &mut env).unwrap(),
+        Expr::new_composed(
+            Expr::new_atom(Token::Integer(1)),
+            Expr::new_atom(Token::Integer(2))
+        )
+    );
+}
+
+#[test]
+fn native_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+        Evaluator::eval(
+            "(define FOLD (lambda (g acc xs) (cond ((null xs) acc) (t (FOLD g (g acc (car xs)) (cdr xs))))))
+             (FOLD + 0 '(1 2 3 4))",
+            &mut env
+        )
+        .unwrap(),
+        Expr::new_atom(Token::Integer(10))
+    );
+    assert_eq!(
+        Evaluator::eval("(define FIRST car) (FIRST '(5 6))", &mut env).unwrap(),
+        Expr::new_atom(Token::Integer(5))
+    );
+    assert_eq!(
+        Evaluator::eval("(cons car +)", &mut env)
+            .unwrap()
+            .to_string(),
+        "(#<procedure car> . #<procedure +>)"
+    );
+
+    let mut inner = Rc::new(RefCell::new(Env::extend(env.clone())));
+    assert_eq!(
+        Evaluator::eval("(define car cdr) (car '(1 2))", &mut inner).unwrap(),
+        Evaluator::eval("'(2)", &mut env).unwrap()
+    );
+    assert_eq!(
+        Evaluator::eval("(car '(1 2))", &mut env).unwrap(),
+        Expr::new_atom(Token::Integer(1))
+    );
+
+    // eval and macroexpand are values too, evaluating in the Env of the call
+    assert_eq!(
+        Evaluator::eval("(define E eval) (let ((X 3)) (E '(+ X 1)))", &mut env).unwrap(),
+        Expr::new_atom(Token::Integer(4))
+    );
+    assert_eq!(
+        Evaluator::eval("(let ((eval car)) (eval '(1)))", &mut env).unwrap(),
+        Expr::new_atom(Token::Integer(1))
+    );
+    assert_eq!(
+        Evaluator::eval("(cons eval macroexpand)", &mut env)
+            .unwrap()
+            .to_string(),
+        "(#<procedure eval> . #<procedure macroexpand>)"
+    );
+}
+
+#[test]
+fn define_native_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let calls = Rc::new(RefCell::new(0));
+    let counter = calls.clone();
+    env.borrow_mut()
+        .define_native("lookup-account", 1, move |args| {
+            *counter.borrow_mut() += 1;
+            match &args[0] {
+                Expr::Atom(Token::Integer(42)) => Ok(Expr::new_atom(Token::String("alice".into()))),
+                _ => Ok(NIL),
+            }
+        });
+    env.borrow_mut()
+        .define_native("count", Arity::AtLeast(0), |args| {
+            Ok(Expr::new_atom(Token::Integer(args.len() as i64)))
+        });
+
+    assert_eq!(
+        Evaluator::eval("(cons (lookup-account 42) (lookup-account 7))", &mut env).unwrap(),
+        Expr::new_composed(Expr::new_atom(Token::String("alice".into())), NIL)
+    );
+    assert_eq!(*calls.borrow(), 2);
+    assert_eq!(
+        Evaluator::eval("(cons (count) (count 1 2 3))", &mut env).unwrap(),
+        Expr::new_composed(
+            Expr::new_atom(Token::Integer(0)),
+            Expr::new_atom(Token::Integer(3))
+        )
+    );
+}
+
+#[test]
+fn variadic_math_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let eval = |source: &str, env: &mut Rc<RefCell<Env>>| Evaluator::eval(source, env).unwrap();
+    assert_eq!(eval("(+)", &mut env), Expr::new_atom(Token::Integer(0)));
+    assert_eq!(eval("(*)", &mut env), Expr::new_atom(Token::Integer(1)));
+    assert_eq!(eval("(- 5)", &mut env), Expr::new_atom(Token::Integer(-5)));
+    assert_eq!(
+        eval("(+ 1 2 3 4)", &mut env),
+        Expr::new_atom(Token::Integer(10))
+    );
+    assert_eq!(
+        eval("(- 10 1 2)", &mut env),
+        Expr::new_atom(Token::Integer(7))
+    );
+    assert_eq!(
+        eval("(* 2 3 4)", &mut env),
+        Expr::new_atom(Token::Integer(24))
+    );
+    assert_eq!(
+        eval("(/ 100 5 2)", &mut env),
+        Expr::new_atom(Token::Integer(10))
+    );
+    assert_eq!(eval("(/ 1)", &mut env), Expr::new_atom(Token::Integer(1)));
+}
+
+#[test]
+fn comparison_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let eval = |source: &str, env: &mut Rc<RefCell<Env>>| Evaluator::eval(source, env).unwrap();
+    assert_eq!(eval("(< 1 2 3)", &mut env), TRUE);
+    assert_eq!(eval("(< 1 3 2)", &mut env), FALSE);
+    assert_eq!(eval("(> 3 2 1)", &mut env), TRUE);
+    assert_eq!(eval("(<= 1 1 2)", &mut env), TRUE);
+    assert_eq!(eval("(>= 2 2 3)", &mut env), FALSE);
+    assert_eq!(eval("(= 4 4 4)", &mut env), TRUE);
+    assert_eq!(eval("(= 4 4 5)", &mut env), FALSE);
+    assert_eq!(
+        eval(
+            "(define MAX (lambda (a b) (cond ((> a b) a) (t b)))) (MAX 3 7)",
+            &mut env
+        ),
+        Expr::new_atom(Token::Integer(7))
+    );
+}
+
+#[test]
+fn bignum_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let eval = |source: &str, env: &mut Rc<RefCell<Env>>| {
+        Evaluator::eval(source, env).unwrap().to_string()
+    };
+    assert_eq!(
+        eval(
+            "(define FACT (lambda (n) (cond ((= n 0) 1) (t (* n (FACT (- n 1)))))))
+             (FACT 13)",
+            &mut env
+        ),
+        "6227020800"
+    );
+    assert_eq!(eval("(FACT 25)", &mut env), "15511210043330985984000000");
+    assert_eq!(
+        eval("(+ 9223372036854775807 1)", &mut env),
+        "9223372036854775808"
+    );
+    assert_eq!(
+        eval("(- -9223372036854775808)", &mut env),
+        "9223372036854775808"
+    );
+    assert_eq!(
+        Evaluator::eval("(/ (FACT 25) (FACT 24))", &mut env).unwrap(),
+        Expr::new_atom(Token::Integer(25))
+    );
+    assert_eq!(eval("(< 1 (FACT 30) (FACT 31))", &mut env), "t");
+}
+
+#[test]
+fn float_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let eval = |source: &str, env: &mut Rc<RefCell<Env>>| {
+        Evaluator::eval(source, env).unwrap().to_string()
+    };
+    assert_eq!(eval("(* 2 3.5)", &mut env), "7.0");
+    assert_eq!(eval("(+ 0.1 0.2)", &mut env), "0.30000000000000004");
+    assert_eq!(eval("(/ 7 2.0)", &mut env), "3.5");
+    assert_eq!(eval("(- 1e-9)", &mut env), "-1e-9");
+    assert_eq!(eval("(/ 1.0 0.0)", &mut env), "+inf.0");
+    assert_eq!(eval("(< 1 1.5 2)", &mut env), "t");
+    assert_eq!(eval("(= 2 2.0)", &mut env), "t");
+    assert_eq!(eval("(eq 2 2.0)", &mut env), "f");
+    assert_eq!(eval("(exact->inexact 3)", &mut env), "3.0");
+    assert_eq!(eval("(inexact->exact 3.0)", &mut env), "3");
+    assert_eq!(eval("(floor -2.5)", &mut env), "-3.0");
+    assert_eq!(eval("(ceiling 2.1)", &mut env), "3.0");
+    assert_eq!(eval("(round 2.5)", &mut env), "2.0");
+    assert_eq!(eval("(round 3.5)", &mut env), "4.0");
+    assert_eq!(eval("(truncate -2.7)", &mut env), "-2.0");
+    assert_eq!(eval("(floor 7)", &mut env), "7");
+    assert_eq!(eval("(sqrt 16)", &mut env), "4");
+    assert_eq!(eval("(sqrt 2)", &mut env), "1.4142135623730951");
+    assert_eq!(eval("(sqrt 6.25)", &mut env), "2.5");
+}
+
+#[test]
+fn rational_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let eval = |source: &str, env: &mut Rc<RefCell<Env>>| {
+        Evaluator::eval(source, env).unwrap().to_string()
+    };
+    assert_eq!(eval("(/ 1 3)", &mut env), "1/3");
+    assert_eq!(eval("(/ 6 4)", &mut env), "3/2");
+    assert_eq!(eval("(/ -6 4)", &mut env), "-3/2");
+    assert_eq!(eval("(/ 6 -3)", &mut env), "-2");
+    assert_eq!(eval("(+ 1/3 2/3)", &mut env), "1");
+    assert_eq!(eval("(* 2/4 3)", &mut env), "3/2");
+    assert_eq!(eval("(- 1/2 1/3)", &mut env), "1/6");
+    assert_eq!(eval("(/ 1/2)", &mut env), "2");
+    assert_eq!(eval("(+ 1/2 0.25)", &mut env), "0.75");
+    assert_eq!(eval("(< 1/3 0.34 1/2)", &mut env), "t");
+    assert_eq!(eval("(= 1/2 0.5)", &mut env), "t");
+    assert_eq!(eval("(numerator 6/4)", &mut env), "3");
+    assert_eq!(eval("(denominator 6/4)", &mut env), "2");
+    assert_eq!(eval("(denominator 5)", &mut env), "1");
+    assert_eq!(eval("(denominator 0.75)", &mut env), "4.0");
+    assert_eq!(eval("(inexact->exact 2.5)", &mut env), "5/2");
+    assert_eq!(eval("(exact->inexact 1/4)", &mut env), "0.25");
+    assert_eq!(eval("(floor -7/2)", &mut env), "-4");
+    assert_eq!(eval("(ceiling 7/2)", &mut env), "4");
+    assert_eq!(eval("(round 5/2)", &mut env), "2");
+    assert_eq!(eval("(round 7/2)", &mut env), "4");
+    assert_eq!(eval("(truncate -7/2)", &mut env), "-3");
+    assert_eq!(eval("(sqrt 9/4)", &mut env), "3/2");
+}
+
+#[test]
+fn let_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let eval = |source: &str, env: &mut Rc<RefCell<Env>>| {
+        Evaluator::eval(source, env).unwrap().to_string()
+    };
+    assert_eq!(
+        eval("(define X 10) (let ((X 1) (Y X)) (+ X Y))", &mut env),
+        "11"
+    );
+    assert_eq!(
+        eval(
+            "(let* ((X 1) (Y (+ X 1))) (define Z 3) (+ X Y Z))",
+            &mut env
+        ),
+        "6"
+    );
+    assert_eq!(eval("X", &mut env), "10");
+    assert_eq!(
+        eval(
+            "(letrec ((EVEN (lambda (n) (cond ((= n 0) t) (t (ODD (- n 1))))))
+                      (ODD (lambda (n) (cond ((= n 0) f) (t (EVEN (- n 1)))))))
+               (cons (EVEN 10) (ODD 7)))",
+            &mut env
+        ),
+        "(t . t)"
+    );
+    assert_eq!(
+        eval(
+            "(let LOOP ((i 0) (acc '()))
+               (cond ((= i 3) acc)
+                     (t (LOOP (+ i 1) (cons i acc)))))",
+            &mut env
+        ),
+        "(2 1 0)"
+    );
+
+    // A named let leaves nothing behind referring to its env
+    let count = Rc::strong_count(&env);
+    for _ in 0..10 {
+        eval("(let LOOP ((i 0)) (if (= i 3) i (LOOP (+ i 1))))", &mut env);
+    }
+    assert_eq!(Rc::strong_count(&env), count);
+}
+
+#[test]
+fn tail_call_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let eval = |source: &str, env: &mut Rc<RefCell<Env>>| {
+        Evaluator::eval(source, env).unwrap().to_string()
+    };
+    assert_eq!(
+        eval(
+            "(define COUNT (lambda (n acc) (cond ((= n 0) acc) (t (COUNT (- n 1) (+ acc 1))))))
+             (COUNT 100000 0)",
+            &mut env
+        ),
+        "100000"
+    );
+    assert_eq!(
+        eval(
+            "(define EVEN? (lambda (n) (if (= n 0) t (ODD? (- n 1)))))
+             (define ODD? (lambda (n) (if (= n 0) f (EVEN? (- n 1)))))
+             (EVEN? 100001)",
+            &mut env
+        ),
+        "f"
+    );
+    assert_eq!(
+        eval(
+            "(let LOOP ((i 0) (sum 0))
+               (define NEXT (+ i 1))
+               (if (> i 100000) sum (LOOP NEXT (+ sum 1))))",
+            &mut env
+        ),
+        "100001"
+    );
+}
+
+#[test]
+fn if_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let eval = |source: &str, env: &mut Rc<RefCell<Env>>| {
+        Evaluator::eval(source, env).unwrap().to_string()
+    };
+    assert_eq!(eval("(if (< 1 2) 'yes 'no)", &mut env), "yes");
+    assert_eq!(eval("(if (> 1 2) 'yes 'no)", &mut env), "no");
+    assert_eq!(eval("(if (> 1 2) 'yes)", &mut env), "nil");
+}
+
+#[test]
+fn deep_recursion_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+        Evaluator::eval(
+            "(define SUM (lambda (n) (if (= n 0) 0 (+ n (SUM (- n 1))))))
+             (SUM 100000)",
+            &mut env
+        )
+        .unwrap()
+        .to_string(),
+        "5000050000"
+    );
+}
+
+#[test]
+fn long_list_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let source = format!("(define XS '({}2)) (car XS)", "1 ".repeat(1_000_000));
+    assert_eq!(Evaluator::eval(&source, &mut env).unwrap().to_string(), "1");
+    let xs = env.borrow().get("XS").unwrap();
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    std::hash::Hash::hash(&xs, &mut hasher);
+    assert_eq!(xs, xs.clone());
+
+    // Deeply nested templates expand and print without recursion
+    let depth = 50_000;
+    let source = format!("`{},X{}", "(".repeat(depth), ")".repeat(depth));
+    env.borrow_mut().set("X", Expr::new_atom(Token::Integer(1)));
+    assert_eq!(
+        Evaluator::eval(&source, &mut env).unwrap().to_string(),
+        format!("{}1{}", "(".repeat(depth), ")".repeat(depth))
+    );
+}
+
+#[test]
+fn macro_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let mut eval = |source: &str| Evaluator::eval(source, &mut env).unwrap().to_string();
+    eval("(defmacro UNLESS (test . body) `(if ,test nil (let () ,@body)))");
+    eval("(defmacro SWAP! (a b) `(let ((TMP ,a)) (set! ,a ,b) (set! ,b TMP)))");
+    assert_eq!(eval("(UNLESS f 1 2)"), "2");
+    assert_eq!(eval("(UNLESS t 1 2)"), "nil");
+    assert_eq!(
+        eval("(define X 1) (define Y 2) (SWAP! X Y) (cons X Y)"),
+        "(2 . 1)"
+    );
+    assert_eq!(
+        eval("(macroexpand-1 '(SWAP! X Y))"),
+        "(let ((TMP X)) (set! X Y) (set! Y TMP))"
+    );
+
+    // Expansion repeats only while the head form is a macro use
+    eval("(defmacro MY-OR (a . rest) (if (null rest) a `(let ((V ,a)) (if (eq V t) t (MY-OR ,@rest)))))");
+    eval("(defmacro OR2 (a b) `(MY-OR ,a ,b))");
+    assert_eq!(eval("(MY-OR f f t)"), "t");
+    assert_eq!(
+        eval("(macroexpand '(OR2 1 2))"),
+        "(let ((V 1)) (if (eq V t) t (MY-OR 2)))"
+    );
+    assert_eq!(eval("(macroexpand '(car X))"), "(car X)");
+
+    // Macros are scoped like variables and can be redefined
+    assert_eq!(eval("(let ((SWAP! cons)) (SWAP! 1 2))"), "(1 . 2)");
+    assert_eq!(
+        eval("(let () (defmacro SWAP! (a b) `(cons ,b ,a)) (SWAP! 1 2))"),
+        "(2 . 1)"
+    );
+    assert_eq!(
+        eval("(macroexpand-1 '(SWAP! X Y))"),
+        "(let ((TMP X)) (set! X Y) (set! Y TMP))"
+    );
+    eval("(defmacro SWAP! (a b) `(quote (,b ,a)))");
+    assert_eq!(eval("(SWAP! X Y)"), "(Y X)");
+}
+
+#[test]
+fn syntax_rules_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let mut eval = |source: &str| Evaluator::eval(source, &mut env).unwrap().to_string();
+    eval(
+        "(define-syntax SWAP! (syntax-rules () ((_ a b) (let ((TMP a)) (set! a b) (set! b TMP)))))",
+    );
+    eval("(define-syntax MY-OR (syntax-rules () ((_) f) ((_ e) e) ((_ e r ...) (let ((TEMP e)) (if (eq TEMP t) t (MY-OR r ...))))))");
+    eval("(define-syntax MY-LET (syntax-rules () ((_ ((n v) ...) body ...) ((lambda (n ...) body ...) v ...))))");
+
+    // Names the template binds do not capture user variables
+    assert_eq!(
+        eval("(define TMP 1) (define Y 2) (SWAP! TMP Y) (cons TMP Y)"),
+        "(2 . 1)"
+    );
+    assert_eq!(eval("(let ((TEMP t)) (MY-OR f TEMP))"), "t");
+    assert_eq!(eval("(MY-OR)"), "f");
+
+    // Free names of the template refer to the definition, not the use site
+    eval("(define ONE 1) (define-syntax ADD-ONE (syntax-rules () ((_ x) (+ x ONE))))");
+    assert_eq!(eval("(let ((ONE 100)) (ADD-ONE 5))"), "6");
+    eval("(define COUNTER 0) (define-syntax BUMP! (syntax-rules () ((_) (set! COUNTER (+ COUNTER 1)))))");
+    assert_eq!(eval("(BUMP!) (let ((COUNTER 10)) (BUMP!)) COUNTER"), "2");
+
+    assert_eq!(eval("(MY-LET ((a 1) (b 2)) (define c 3) (+ a b c))"), "6");
+    assert_eq!(
+        eval("(macroexpand '(MY-LET ((a 1)) a))"),
+        "((lambda (a) a) 1)"
+    );
+    // Renamed symbols print as written in the template
+    assert_eq!(
+        eval("(macroexpand '(SWAP! X Y))"),
+        "(let ((TMP X)) (set! X Y) (set! Y TMP))"
+    );
+    eval("(define-syntax FLIP (syntax-rules () ((_ (a b ...) ...) '((b ... a) ...))))");
+    assert_eq!(eval("(FLIP (1 2 3) (4 5) (6))"), "((2 3 1) (5 4) (6))");
+
+    // Literals match only themselves
+    eval("(define-syntax FOR (syntax-rules (IN) ((_ x IN lst body) (let LOOP ((rest lst)) (if (null rest) nil (let ((x (car rest))) body (LOOP (cdr rest)))))) ((_ x body) body)))");
+    assert_eq!(
+        eval("(define ACC 0) (FOR x IN '(1 2 3) (set! ACC (+ ACC x))) ACC"),
+        "6"
+    );
+    assert_eq!(eval("(FOR x 7)"), "7");
+
+    assert_eq!(
+        eval("(let-syntax ((ADD-ONE (syntax-rules () ((_ x) (- x 1))))) (ADD-ONE 5))"),
+        "4"
+    );
+    assert_eq!(eval("(ADD-ONE 5)"), "6");
+}
+
+#[test]
+fn call_cc_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let mut eval = |source: &str| Evaluator::eval(source, &mut env).unwrap().to_string();
+
+    // Escaping skips the rest of the receiver
+    assert_eq!(eval("(+ 1 (call/cc (lambda (k) (+ 10 (k 5)))))"), "6");
+    assert_eq!(eval("(call/cc (lambda (k) 3))"), "3");
+    assert_eq!(
+        eval(
+            "(define FIND-NEG (lambda (xs)
+               (call-with-current-continuation
+                 (lambda (return)
+                   (let LOOP ((rest xs))
+                     (cond ((null rest) nil)
+                           ((< (car rest) 0) (return (car rest)))
+                           (t (LOOP (cdr rest)))))))))
+             (cons (FIND-NEG '(1 2 -3 4)) (FIND-NEG '(1 2)))"
+        ),
+        "(-3)"
+    );
+
+    // Re-entering resumes the captured computation again, even after the
+    // call that captured it has returned
+    assert_eq!(
+        eval(
+            "(define SAVED nil)
+             (define COUNT 0)
+             (define BUMP (lambda () (+ 100 (call/cc (lambda (k) (set! SAVED k) 0)))))
+             (let ((R (BUMP)))
+               (set! COUNT (+ COUNT 1))
+               (if (< COUNT 3) (SAVED COUNT) (cons R COUNT)))"
+        ),
+        "(102 . 3)"
+    );
+    assert_eq!(
+        eval("(let ((K (call/cc (lambda (k) k)))) (if (eq K 1) 'done (K 1)))"),
+        "done"
+    );
+    assert_eq!(eval("(call/cc (lambda (k) k))"), "#<continuation>");
+
+    // A continuation also runs the top-level forms after its own
+    assert_eq!(
+        eval(
+            "(define ACC '())
+             (define N 0)
+             (set! ACC (cons (call/cc (lambda (k) (set! SAVED k) 0)) ACC))
+             (set! N (+ N 1))
+             (if (< N 3) (SAVED N) ACC)"
+        ),
+        "(2 1 0)"
+    );
+
+    // call/cc is a procedure value like any other
+    assert_eq!(eval("call/cc"), "#<procedure call/cc>");
+    assert_eq!(
+        eval("(define CC call/cc) (+ 1 (CC (lambda (k) (k 2))))"),
+        "3"
+    );
+    assert_eq!(eval("(apply call/cc (lambda (k) (k 4)))"), "4");
+    assert_eq!(eval("(let ((call/cc car)) (call/cc '(1 2)))"), "1");
+}
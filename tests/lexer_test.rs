@@ -1,4 +1,6 @@
 use lisp::Lexer;
+use lisp::LispError;
+use lisp::Source;
 use lisp::Token;
 use std::collections::VecDeque;
 
@@ -21,3 +23,169 @@ fn tokenize_test() {
         .collect::<VecDeque<Token>>(),
     );
 }
+
+#[test]
+fn tokenize_spanned_test() {
+    let source = Source::new("<test>", "(car\n  sqr)");
+    let spans = Lexer::tokenize_spanned(source.clone())
+        .unwrap()
+        .into_iter()
+        .map(|(_, span)| (span.line, span.column, span.range))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        spans,
+        vec![(1, 1, 0..1), (1, 2, 1..4), (2, 3, 7..10), (2, 6, 10..11)]
+    );
+}
+
+#[test]
+fn tokenize_string_test() {
+    assert_eq!(
+        Lexer::tokenize("(cons \"hello world\" \"tab\\t\\\"q\\\" \\\\ \\u{48}\ni\")").unwrap(),
+        vec![
+            Token::LParen,
+            Token::Symbol("cons".into()),
+            Token::String("hello world".into()),
+            Token::String("tab\t\"q\" \\ H\ni".into()),
+            Token::RParen,
+        ]
+        .into_iter()
+        .collect::<VecDeque<Token>>(),
+    );
+    assert!(Lexer::tokenize("(car \"open)").is_err());
+    assert!(Lexer::tokenize("(car \"\\q\")").is_err());
+    assert!(Lexer::tokenize("(car \"\\u{110000}\")").is_err());
+}
+
+#[test]
+fn tokenize_comment_test() {
+    assert_eq!(
+        Lexer::tokenize(
+            "; leading (comment\n(cons #| block #| nested ( |# |# 1 ; trailing )\n #;(car (x)) #; #; 2 3 4)"
+        )
+        .unwrap(),
+        vec![
+            Token::LParen,
+            Token::Symbol("cons".into()),
+            Token::Integer(1),
+            Token::Integer(4),
+            Token::RParen,
+        ]
+        .into_iter()
+        .collect::<VecDeque<Token>>(),
+    );
+    assert!(Lexer::tokenize("(car #| open)").is_err());
+    // Only input ending inside a datum comment may still be completed
+    let err = Lexer::tokenize("#;(define x").unwrap_err();
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::MissingDatum
+    );
+    let err = Lexer::tokenize("(car x #;)").unwrap_err();
+    assert_eq!(
+        err.downcast::<LispError>().unwrap(),
+        LispError::UnexpectedToken(Token::RParen)
+    );
+    assert_eq!(
+        Lexer::tokenize("(1 #; 'x 2 #; `(a ,@b) #; ,',y 3) #; 'foo 4").unwrap(),
+        Lexer::tokenize("(1 2 3) 4").unwrap(),
+    );
+    assert!(Lexer::tokenize("(car #; ')").is_err());
+}
+
+#[test]
+fn integer_test() {
+    let tokens =
+        Lexer::tokenize("2147483648 -9223372036854775808 99999999999999999999 1_000").unwrap();
+    assert_eq!(tokens[0], Token::Integer(2147483648));
+    assert_eq!(tokens[1], Token::Integer(i64::MIN));
+    assert!(matches!(tokens[2], Token::BigInt(_)));
+    assert_eq!(tokens[2].to_string(), "99999999999999999999");
+    assert_eq!(tokens[3], Token::Integer(1000));
+}
+
+#[test]
+fn float_test() {
+    let tokens = Lexer::tokenize("2.75 1e-9 -.5 +inf.0 1e3 ...").unwrap();
+    assert_eq!(tokens[0], Token::Float(2.75));
+    assert_eq!(tokens[1], Token::Float(1e-9));
+    assert_eq!(tokens[2], Token::Float(-0.5));
+    assert_eq!(tokens[3], Token::Float(f64::INFINITY));
+    assert_eq!(tokens[4], Token::Float(1000.0));
+    assert_eq!(tokens[5], Token::Symbol("...".into()));
+    assert_eq!(Token::Float(1e21).to_string(), "1e21");
+    assert_eq!(Token::Float(2.0).to_string(), "2.0");
+}
+
+#[test]
+fn rational_test() {
+    let tokens = Lexer::tokenize("1/3 -2/4 4/2 a/b").unwrap();
+    assert_eq!(tokens[0].to_string(), "1/3");
+    assert!(matches!(tokens[0], Token::Rational(_)));
+    assert_eq!(tokens[1].to_string(), "-1/2");
+    assert_eq!(tokens[2], Token::Integer(2));
+    assert_eq!(tokens[3], Token::Symbol("a/b".into()));
+}
+
+#[test]
+fn numeric_literal_test() {
+    let tokens = Lexer::tokenize(
+        "#x1F #X-ff #b1010 #o17 #d99 #e1.5 #i1/4 #x#e10 #e#b101 1_000_000 #xdead_beef 2.5e3 1E-2",
+    )
+    .unwrap();
+    assert_eq!(
+        tokens.iter().map(Token::to_string).collect::<Vec<_>>(),
+        vec![
+            "31",
+            "-255",
+            "10",
+            "15",
+            "99",
+            "3/2",
+            "0.25",
+            "16",
+            "5",
+            "1000000",
+            "3735928559",
+            "2500.0",
+            "0.01"
+        ]
+    );
+
+    let tokens = Lexer::tokenize("1e999999999 1e-999999999 #e1e100").unwrap();
+    assert_eq!(tokens[0], Token::Float(f64::INFINITY));
+    assert_eq!(tokens[1], Token::Float(0.0));
+    assert_eq!(tokens[2].to_string(), format!("1{}", "0".repeat(100)));
+
+    for malformed in [
+        "12abc", "1e", "1/0", "1/-2", "1__0", "1_", "#x1g", "#b102", "#e#e1", "#x1.5", "1.2.3",
+        "#e+inf.0", "-1a",
+    ] {
+        let err = Lexer::tokenize(malformed).unwrap_err();
+        assert_eq!(
+            err.downcast::<LispError>().unwrap(),
+            LispError::MalformedNumber(malformed.into()),
+            "{}",
+            malformed
+        );
+    }
+
+    // Exact decimals are limited to exponents of 4096 in magnitude
+    let tokens = Lexer::tokenize("#e1e4096 #e1e-4096").unwrap();
+    assert_eq!(tokens[0].to_string(), format!("1{}", "0".repeat(4096)));
+    assert!(matches!(tokens[1], Token::Rational(_)));
+    for large in [
+        "#e1e5000",
+        "#e1e-4097",
+        "#e1e999999999",
+        "#e1e99999999999999999999",
+    ] {
+        let err = Lexer::tokenize(large).unwrap_err();
+        assert_eq!(
+            err.downcast::<LispError>().unwrap(),
+            LispError::ExponentTooLarge(large.into()),
+            "{}",
+            large
+        );
+    }
+}
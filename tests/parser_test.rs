@@ -1 +1,91 @@
+use lisp::{consts::*, Expr, Parser, Printer, Token};
 
+#[test]
+fn quote_shorthand_test() {
+    assert_eq!(
+        Parser::parse("(car '(1 2))").unwrap(),
+        Parser::parse("(car (quote (1 2)))").unwrap()
+    );
+    assert_eq!(
+        Parser::parse("`(1 ,x ,@(y))").unwrap(),
+        Parser::parse("(quasiquote (1 (unquote x) (unquote-splicing (y))))").unwrap()
+    );
+    assert!(Parser::parse("(car ')").is_err());
+}
+
+#[test]
+fn parse_program_test() {
+    assert_eq!(
+        Parser::parse_program("(define X 1)\nx\n'(car x)").unwrap(),
+        vec![
+            Parser::parse("(define X 1)").unwrap(),
+            Parser::parse("x").unwrap(),
+            Parser::parse("'(car x)").unwrap(),
+        ]
+    );
+    assert!(Parser::parse_program("").unwrap().is_empty());
+
+    let path = std::env::temp_dir().join(format!("parse_program_test_{}.lisp", std::process::id()));
+    std::fs::write(&path, "(define X 1)\n(car '(x))\n").unwrap();
+    let exprs = Parser::parse_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        exprs.unwrap(),
+        vec![
+            Parser::parse("(define X 1)").unwrap(),
+            Parser::parse("(car '(x))").unwrap(),
+        ]
+    );
+}
+
+#[test]
+fn deep_nesting_test() {
+    let depth = 100_000;
+    let source = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    let expr = Parser::parse(&source).unwrap();
+    assert_eq!(expr, Parser::parse(&source).unwrap());
+    assert_eq!(expr.to_string(), source);
+    assert_eq!(Printer::pretty(&expr, Printer::DEFAULT_WIDTH), source);
+    assert!(Parser::parse(&source[1..]).is_err());
+}
+
+#[test]
+fn proper_list_test() {
+    let int = |n| Expr::new_atom(Token::Integer(n));
+    assert_eq!(
+        Parser::parse("((1) 2 3)").unwrap(),
+        Expr::new_composed(
+            Expr::new_composed(int(1), NIL),
+            Expr::new_composed(int(2), Expr::new_composed(int(3), NIL))
+        )
+    );
+    assert_eq!(Parser::parse("()").unwrap(), NIL);
+}
+
+#[test]
+fn display_round_trip_test() {
+    for source in [
+        "(define SQR (lambda (x) (* x x)))",
+        "(1 (2 . 3) . 4)",
+        "((nil) () t f -7)",
+        "(quote (a b))",
+        "\"tab\\t \\\"quoted\\\" \\\\ \\u{7f}\"",
+    ] {
+        let expr = Parser::parse(source).unwrap();
+        assert_eq!(Parser::parse(expr.to_string()).unwrap(), expr);
+    }
+    assert_eq!(
+        Parser::parse("(1 . (2 . (3 . nil)))").unwrap().to_string(),
+        "(1 2 3)"
+    );
+    assert_eq!(
+        Expr::new_composed(
+            Expr::new_atom(Token::Integer(1)),
+            Expr::new_atom(Token::Integer(2))
+        )
+        .to_string(),
+        "(1 . 2)"
+    );
+    assert!(Parser::parse("(1 . 2 3)").is_err());
+    assert!(Parser::parse("(. 2)").is_err());
+}
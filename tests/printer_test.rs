@@ -0,0 +1,58 @@
+use lisp::{Parser, Printer};
+
+#[test]
+fn pretty_flat_test() {
+    let expr = Parser::parse("(define SQR (lambda (x) (* x x)))").unwrap();
+    assert_eq!(
+        Printer::pretty(&expr, Printer::DEFAULT_WIDTH),
+        expr.to_string()
+    );
+
+    // Measured widths match the printed ones exactly
+    let expr = Parser::parse("(\"λé\" (a . (b c . d)) () 1.5)").unwrap();
+    let width = expr.to_string().chars().count();
+    assert_eq!(Printer::pretty(&expr, width), expr.to_string());
+    assert_ne!(Printer::pretty(&expr, width - 1), expr.to_string());
+}
+
+#[test]
+fn pretty_form_test() {
+    let expr = Parser::parse(
+        "(define SUM (lambda (x) (cond ((eq x 0) 0) (t (+ x (apply SUM (- x 1)))))))",
+    )
+    .unwrap();
+    assert_eq!(
+        Printer::pretty(&expr, 40),
+        "(define SUM
+  (lambda (x)
+    (cond ((eq x 0) 0)
+          (t (+ x (apply SUM (- x 1)))))))"
+    );
+}
+
+#[test]
+fn pretty_data_test() {
+    let expr = Parser::parse("(1 2 3 4 5 6 7 8 9 10 (a . b) 11 12 . 13)").unwrap();
+    let pretty = Printer::pretty(&expr, 16);
+    assert_eq!(
+        pretty,
+        "(1 2 3 4 5 6 7
+ 8 9 10 (a . b)
+ 11 12 . 13)"
+    );
+    assert_eq!(Parser::parse(pretty).unwrap(), expr);
+}
+
+#[test]
+fn pretty_let_test() {
+    let expr = Parser::parse(
+        "(let LOOP ((i 0) (acc 1)) (cond ((= i 5) acc) (t (LOOP (+ i 1) (* acc 2)))))",
+    )
+    .unwrap();
+    assert_eq!(
+        Printer::pretty(&expr, 40),
+        "(let LOOP ((i 0) (acc 1))
+  (cond ((= i 5) acc)
+        (t (LOOP (+ i 1) (* acc 2)))))"
+    );
+}